@@ -29,6 +29,7 @@ pub enum Message {
 static INDIV_CELL_INDEX : usize = 0;
 static SURVIVE_CELL_INDEX : usize = 1;
 static PHEROMONE_CELL_INDEX : usize = 2;
+static FOOD_CELL_INDEX : usize = 3;
 
 
 fn main() -> Result<(), failure::Error> {
@@ -38,7 +39,7 @@ fn main() -> Result<(), failure::Error> {
         .genome_length(10)
         .criteria(sc::SurvivalCriteria::PheromoneInterval(20, 100))
         .mutation_rate(0.1)
-        .build();
+        .build()?;
 
     sim.initialize_first_generation(None);
 
@@ -56,7 +57,7 @@ fn main() -> Result<(), failure::Error> {
         Model {
             sim,
             run_state: RunState::Paused,
-            cells_info: CellsInfo { cells: vec![vec![], vec![], vec![]] },
+            cells_info: CellsInfo { cells: vec![vec![], vec![], vec![], vec![]] },
             generation_text: LiveTextString { text: "Generation 0".to_string(), scale: 1.0 },
             stat_text: LiveTextString { text: "".to_string(), scale: 0.8 }
         };
@@ -130,14 +131,11 @@ impl Model {
         // individuals cells
         for indiv in &self.sim.world().individuals {
             let coord = index_functions::index_to_coord(indiv.grid_index, self.sim.world().grid.size);
-            let color = gene_functions::genome_to_rgb(&indiv.genome);
-            self.cells_info.cells[INDIV_CELL_INDEX].push(Cell { cell_type: CellType::Square, color: Color::RGB(color.0, color.1, color.2), point: Point::new(coord.x, coord.y) })
+            let color = indiv_color(&self.sim, indiv);
+            self.cells_info.cells[INDIV_CELL_INDEX].push(Cell { cell_type: CellType::Square, color, point: Point::new(coord.x, coord.y) })
         }
 
-        // survive cells
-        for coord in &self.sim.survive_cells() {
-            self.cells_info.cells[SURVIVE_CELL_INDEX].push(Cell { cell_type: CellType::Square, color: Color::RGBA(53, 212, 63, 50), point: Point::new(coord.x, coord.y) })
-        }
+        self.update_survive_cells();
 
 
         // INITIAL NO PHEROMONES
@@ -151,12 +149,15 @@ impl Model {
             self.cells_info.cells[INDIV_CELL_INDEX][i].point = Point::new(coord.x, coord.y);
 
 
-            let color = gene_functions::genome_to_rgb(&indiv.genome);
-            self.cells_info.cells[INDIV_CELL_INDEX][i].color = Color::RGB(color.0, color.1, color.2);
+            self.cells_info.cells[INDIV_CELL_INDEX][i].color = indiv_color(&self.sim, indiv);
         }
 
-        // clear old pheromones
+        // Criteria can depend on pheromones, so survive cells change between steps
+        self.update_survive_cells();
+
+        // clear old pheromones and food
         self.cells_info.cells[PHEROMONE_CELL_INDEX].clear();
+        self.cells_info.cells[FOOD_CELL_INDEX].clear();
 
         for (i, tile) in self.sim.world().grid.tiles.iter().enumerate() {
 
@@ -174,18 +175,65 @@ impl Model {
                         color: Color::RGBA(125, 65, 204, alpha as u8),
                         point: Point::new(coord.x, coord.y) });
             }
+
+            if tile.food > 0 {
+                let alpha = tile.food as f32 / 3.0;
+
+                let coord = index_functions::index_to_coord(i, self.sim.world().grid.size);
+
+                self.cells_info.cells[FOOD_CELL_INDEX].push(
+                    Cell {
+                        cell_type: CellType::Square,
+                        color: Color::RGBA(212, 160, 23, alpha as u8),
+                        point: Point::new(coord.x, coord.y) });
+            }
+        }
+    }
+
+
+    pub fn update_survive_cells(&mut self) {
+        self.cells_info.cells[SURVIVE_CELL_INDEX].clear();
+
+        for coord in &self.sim.survive_cells() {
+            self.cells_info.cells[SURVIVE_CELL_INDEX].push(Cell { cell_type: CellType::Square, color: Color::RGBA(53, 212, 63, 50), point: Point::new(coord.x, coord.y) })
         }
     }
 
 
     pub fn update_stats(&mut self) {
+        let survival_rate = self.sim.last_survival_rate();
         let config = self.sim.config();
 
-        self.stat_text.text = format!("{:#?}\nSurvival rate: {:.0} %", config, self.sim.last_survival_rate());
+        self.stat_text.text = format!("{:#?}\nSurvival rate: {:.0} %", config, survival_rate);
+
+        // Per species stats, when there is more than one
+        if self.sim.species().len() > 1 {
+            let generation = usize::min(self.sim.generation(), self.sim.stats().len() - 1);
+            let stats = &self.sim.stats()[generation];
+
+            for (i, species) in self.sim.species().iter().enumerate() {
+                let rate = stats.species.get(i).map(|s| s.survival_rate).unwrap_or(0.0);
+                self.stat_text.text += &format!("\n{}: {:.0} %", species.name, rate);
+            }
+        }
+
+        self.stat_text.text += &format!("\nGenetic species: {}", self.sim.speciation().living_clusters().count());
     }
 }
 
 
+/// Species colour when there are multiple species, otherwise the colour of the genetic cluster
+fn indiv_color(sim: &sim_lib::simulation::Simulation, indiv: &Individual) -> Color {
+    let color = if sim.species().len() > 1 {
+        sim.species()[indiv.species].color
+    } else {
+        speciation::cluster_color(indiv.cluster)
+    };
+
+    Color::RGB(color.0, color.1, color.2)
+}
+
+
 
 impl gls::State<Message> for Model {
 
@@ -202,6 +250,23 @@ impl gls::State<Message> for Model {
             },
             Message::GridClick(point) => {
                 println!("Grid clicked at: {:?}", point);
+
+                // Click points are (row, column)
+                let coord = Coord { x: point.y, y: point.x };
+                let size = self.sim.world().grid.size;
+
+                if coord.x < size.x && coord.y < size.y {
+                    let grid_index = index_functions::coord_to_index(coord, size);
+
+                    // Brain of the clicked individual as a graphviz file, render with: dot -Tpng brain_<id>.dot
+                    if let Some(indiv_index) = self.sim.world().grid.tiles[grid_index].individual_index {
+                        let path = format!("brain_{}.dot", self.sim.world().individuals[indiv_index].id);
+                        match std::fs::write(&path, self.sim.network_view(indiv_index).pruned().to_dot()) {
+                            Ok(()) => println!("Brain written to {}", path),
+                            Err(e) => println!("Could not write {}: {}", path, e),
+                        }
+                    }
+                }
             }
             Message::RunSingle => {
                 self.run_state = RunState::RunSingleGen;
@@ -224,8 +289,7 @@ impl gls::State<Message> for Model {
                 let indivs = &self.sim.world().individuals;
 
                 for i in self.sim.surviving_indexes() {
-                    let color = gene_functions::genome_to_rgb(&indivs[i].genome);
-                    self.cells_info.cells[INDIV_CELL_INDEX][i].color = Color::RGB(color.0, color.1, color.2);
+                    self.cells_info.cells[INDIV_CELL_INDEX][i].color = indiv_color(&self.sim, &indivs[i]);
                 }
             },
         }
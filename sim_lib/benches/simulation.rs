@@ -0,0 +1,140 @@
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion};
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+
+use sim_lib::action_neurons;
+use sim_lib::gene_functions;
+use sim_lib::network::Network;
+use sim_lib::network_batch::NetworkBatch;
+use sim_lib::simulation::SimulationBuilder;
+use sim_lib::species::Species;
+use sim_lib::survival_criteria as sc;
+use sim_lib::*;
+
+const SEED: u64 = 42;
+
+/// World of the given size with population individuals on random tiles
+fn populated_world(size: usize, population: usize) -> World {
+    let mut sim = SimulationBuilder::new(size, size)
+        .population_size(population)
+        .seed(SEED)
+        .build()
+        .unwrap();
+    sim.initialize_first_generation(None);
+    sim.world().clone()
+}
+
+fn step(c: &mut Criterion) {
+    let mut group = c.benchmark_group("step_single_thread");
+
+    for &(size, population) in &[(64, 100), (128, 1_000), (256, 10_000)] {
+        // Never reaches the end of the generation, so only steps are measured
+        let mut sim = SimulationBuilder::new(size, size)
+            .population_size(population)
+            .generation_steps(usize::MAX)
+            .seed(SEED)
+            .build()
+            .unwrap();
+        sim.initialize_first_generation(None);
+
+        group.bench_with_input(BenchmarkId::new(format!("{}x{}", size, size), population), &population, |b, _| {
+            b.iter(|| sim.step_single_thread())
+        });
+    }
+
+    group.finish();
+}
+
+fn network(c: &mut Criterion) {
+    let mut rng = StdRng::seed_from_u64(SEED);
+    let config = Configuration::default();
+    let species = Species::new("bench", 0);
+    let sensor_neurons = &species.sensor_neurons;
+    let genome = gene_functions::random_genome(&mut rng, config.genome_length);
+
+    c.bench_function("initialize_from_genome", |b| {
+        let mut network = Network::empty();
+        b.iter(|| {
+            network.initialize_from_genome(black_box(&genome), &species)
+        })
+    });
+
+    let world = populated_world(128, 1);
+    let mut network = Network::empty();
+    network.initialize_from_genome(&genome, &species);
+
+    c.bench_function("run", |b| {
+        b.iter(|| network.run(sensor_neurons, &world, &world.individuals[0]))
+    });
+
+    let world = populated_world(256, 10_000);
+    let networks: Vec<Network> = world
+        .individuals
+        .iter()
+        .map(|indiv| {
+            let mut network = Network::empty();
+            network.initialize_from_genome(&indiv.genome, &species);
+            network.prune();
+            network
+        })
+        .collect();
+
+    let mut batch = NetworkBatch::new();
+    batch.compile(networks.iter().enumerate().map(|(i, network)| (network, &sensor_neurons[..], i)));
+
+    c.bench_function("network_batch_run/10000", |b| b.iter(|| batch.run(&world).len()));
+}
+
+fn pheromones(c: &mut Criterion) {
+    let mut world = populated_world(256, 1_000);
+
+    // same as the decay in step_single_thread, on a fresh copy of a grid with pheromones everywhere
+    let mut grid = world.grid.clone();
+    for index in 0..(grid.size.x * grid.size.y) {
+        grid.increment_pheromone(index, 10);
+    }
+
+    c.bench_function("pheromone_decay", |b| {
+        b.iter_batched_ref(
+            || grid.clone(),
+            |grid| {
+                for index in 0..(grid.size.x * grid.size.y) {
+                    grid.decrement_pheromone(index, 1);
+                }
+            },
+            BatchSize::LargeInput,
+        )
+    });
+
+    c.bench_function("emit_pheromones", |b| {
+        b.iter(|| {
+            for indiv_index in 0..world.individuals.len() {
+                let activation = Activation { action: Action::EmitPheromones, weight: 1.0, indiv_index };
+                action_neurons::perform_action(&activation, &mut world);
+            }
+        })
+    });
+}
+
+fn criteria(c: &mut Criterion) {
+    let world = populated_world(256, 10_000);
+
+    let criterias = [
+        ("border", sc::SurvivalCriteria::Border(0.1)),
+        ("center_or_corners", sc::SurvivalCriteria::Center(Coord { x: 128, y: 128 }, 50).or(sc::SurvivalCriteria::Corners(0.2))),
+        ("min_neighbours", sc::SurvivalCriteria::MinNeighbours(2, 3)),
+    ];
+
+    for (name, criteria) in &criterias {
+        c.bench_function(&format!("survive_cells/{}", name), |b| {
+            b.iter(|| sc::survive_cells(&world, criteria))
+        });
+
+        c.bench_function(&format!("surviving_indexes/{}", name), |b| {
+            b.iter(|| sc::surviving_indexes(&world, criteria))
+        });
+    }
+}
+
+criterion_group!(benches, step, network, pheromones, criteria);
+criterion_main!(benches);
@@ -0,0 +1,42 @@
+//! Evolve for a number of generations and print the brain of one individual as Graphviz dot.
+//!
+//! cargo run --example brain_dot -- <generations> <individual index> [--full]
+//!
+//! Pipe the output to `dot -Tpng -o brain.png` to render it.
+//! Dead nodes are pruned unless --full is given.
+
+use sim_lib::simulation::SimulationBuilder;
+use sim_lib::survival_criteria as sc;
+
+fn main() {
+    let args: Vec<String> = std::env::args().collect();
+
+    let generations: usize = args.get(1).and_then(|a| a.parse().ok()).unwrap_or(10);
+    let index: usize = args.get(2).and_then(|a| a.parse().ok()).unwrap_or(0);
+    let full = args.iter().any(|a| a == "--full");
+
+    let mut sim = SimulationBuilder::new(128, 128)
+        .criteria(sc::SurvivalCriteria::Border(0.1))
+        .mutation_rate(0.01)
+        .build()
+        .expect("Invalid simulation settings");
+
+    sim.initialize_first_generation(None);
+
+    for _ in 0..generations {
+        sim.run_generation();
+    }
+
+    if index >= sim.population_count() {
+        eprintln!("Index {} is out of range, population is {}", index, sim.population_count());
+        std::process::exit(1);
+    }
+
+    let view = sim.network_view(index);
+
+    if full {
+        print!("{}", view.to_dot());
+    } else {
+        print!("{}", view.pruned().to_dot());
+    }
+}
@@ -45,6 +45,12 @@ pub fn perform_action(activation: &Activation, world: &mut World) {
                 return;
             }
 
+            if world.individuals[activation.indiv_index].energy < world.resources.emit_cost {
+                return;
+            }
+
+            world.individuals[activation.indiv_index].energy -= world.resources.emit_cost;
+
             let radius : i32 = 5;
 
             let base_pheromone = 10.0;
@@ -66,6 +72,16 @@ pub fn perform_action(activation: &Activation, world: &mut World) {
                 }
             }
         },
+        Eat => {
+            if activation.weight < 0.2  {
+                return;
+            }
+
+            let grid_index = world.individuals[activation.indiv_index].grid_index;
+            let eaten = world.grid.eat_food(grid_index, world.resources.eat_amount);
+
+            world.individuals[activation.indiv_index].energy += eaten as f32 * world.resources.food_energy;
+        },
         SetOscPeriod => {}
         SetResponsivness => {},
     };
@@ -174,5 +190,35 @@ mod tests {
 
     }
 
+    #[test]
+    fn eat_and_move_cost() {
+
+        let mut world = create_test_world();
+        world.resources.move_cost = 0.4;
+
+        let mut indiv = Individual::new();
+        indiv.grid_index = 64*128 + 64;
+        indiv.energy = 1.0;
+        indiv.forward = Dir::Right;
+
+        let indiv_index = world.add_individual(indiv);
 
+        world.grid.tiles[64*128 + 66].food_source = true;
+        world.grid.tiles[64*128 + 66].food = 100;
+
+        perform_action(&Activation { action: Action::MoveForward, weight: 1.0, indiv_index}, &mut world);
+        perform_action(&Activation { action: Action::MoveForward, weight: 1.0, indiv_index}, &mut world);
+
+        assert_eq!(64*128 + 66, world.individuals[indiv_index].grid_index);
+        assert_relative_eq!(0.2, world.individuals[indiv_index].energy, epsilon = 0.0001);
+
+        // Too little energy to move
+        perform_action(&Activation { action: Action::MoveForward, weight: 1.0, indiv_index}, &mut world);
+        assert_eq!(64*128 + 66, world.individuals[indiv_index].grid_index);
+
+        perform_action(&Activation { action: Action::Eat, weight: 1.0, indiv_index}, &mut world);
+
+        assert_eq!(90, world.grid.tiles[64*128 + 66].food);
+        assert_relative_eq!(0.3, world.individuals[indiv_index].energy, epsilon = 0.0001);
+    }
 }
@@ -0,0 +1,264 @@
+use std::collections::HashMap;
+
+use crate::basic_types::*;
+use crate::network::Network;
+use crate::species::Species;
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PathStats {
+    pub direct: usize, // individuals with a connection straight from the sensor to the action
+    pub via_hidden: usize, // individuals where the sensor reaches the action through hidden neurons
+    pub any: usize, // individuals with either kind of path
+    pub mean_direct_weight: f64, // mean weight of the direct connections
+}
+
+/// Which sensors and actions the population actually uses. Counts are number of individuals.
+#[derive(Debug, Clone, Default)]
+pub struct UsageStats {
+    pub population: usize,
+    pub sensors_connected: HashMap<Sensor, usize>, // sensor has any connection
+    pub sensors_used: HashMap<Sensor, usize>, // sensor has a path to an action
+    pub actions: HashMap<Action, usize>, // action neuron has an input
+    pub paths: HashMap<(Sensor, Action), PathStats>,
+}
+
+impl UsageStats {
+    /// One line per sensor, action and path, in the order of ALL_SENSORS and ALL_ACTIONS
+    pub fn summary(&self) -> String {
+        let pct = |count: usize| count as f64 / usize::max(1, self.population) as f64 * 100.0;
+        let mut res = String::new();
+
+        for sensor in ALL_SENSORS {
+            if let Some(&count) = self.sensors_used.get(sensor) {
+                res += &format!("{:?}: used by {:.0} %\n", sensor, pct(count));
+            }
+        }
+
+        for action in ALL_ACTIONS {
+            if let Some(&count) = self.actions.get(action) {
+                res += &format!("{:?}: used by {:.0} %\n", action, pct(count));
+            }
+        }
+
+        for sensor in ALL_SENSORS {
+            for action in ALL_ACTIONS {
+                if let Some(path) = self.paths.get(&(*sensor, *action)) {
+                    res += &format!(
+                        "{:?} -> {:?}: {:.0} % (direct {:.0} %, mean weight {:.2})\n",
+                        sensor, action, pct(path.any), pct(path.direct), path.mean_direct_weight
+                    );
+                }
+            }
+        }
+
+        res
+    }
+}
+
+/// Decode every genome and count which sensors, actions and paths between them are wired up
+pub fn usage(individuals: &[Individual], species: &[Species]) -> UsageStats {
+    let mut stats = UsageStats { population: individuals.len(), ..Default::default() };
+
+    // Sum of direct weights, to compute the mean at the end
+    let mut weight_sums: HashMap<(Sensor, Action), (f64, usize)> = HashMap::new();
+
+    let mut network = Network::empty();
+
+    for indiv in individuals {
+        let species = &species[indiv.species];
+        network.initialize_from_genome(&indiv.genome, species);
+        let mut reachable = Reachable::new(&network);
+
+        let mut connected = vec![];
+        let mut direct = vec![];
+        let mut reached = vec![];
+        let mut via_hidden = vec![];
+
+        for (sensor_index, neuron, weight) in network.sensor_edges() {
+            let sensor = species.sensor_neurons[sensor_index];
+            connected.push(sensor);
+
+            let is_action = match network.neuron_action(neuron) {
+                Some(action) => {
+                    direct.push((sensor, action));
+                    let sum = weight_sums.entry((sensor, action)).or_insert((0.0, 0));
+                    sum.0 += weight;
+                    sum.1 += 1;
+                    true
+                }
+                None => false,
+            };
+
+            for &action in reachable.actions(neuron) {
+                reached.push((sensor, action));
+                if !is_action {
+                    via_hidden.push((sensor, action));
+                }
+            }
+        }
+
+        for neuron in 0..network.neuron_count() {
+            if let Some(action) = network.neuron_action(neuron) {
+                increment(&mut stats.actions, action);
+            }
+        }
+
+        connected.sort_by_key(|s| *s as usize);
+        connected.dedup();
+        for sensor in connected {
+            increment(&mut stats.sensors_connected, sensor);
+        }
+
+        let mut used: Vec<Sensor> = reached.iter().map(|p| p.0).collect();
+        used.sort_by_key(|s| *s as usize);
+        used.dedup();
+        for sensor in used {
+            increment(&mut stats.sensors_used, sensor);
+        }
+
+        let key = |p: &(Sensor, Action)| (p.0 as usize, p.1 as usize);
+        direct.sort_by_key(key);
+        direct.dedup();
+        reached.sort_by_key(key);
+        reached.dedup();
+        via_hidden.sort_by_key(key);
+        via_hidden.dedup();
+
+        for path in &reached {
+            let path_stats = stats.paths.entry(*path).or_default();
+            path_stats.any += 1;
+
+            if direct.binary_search_by_key(&key(path), key).is_ok() {
+                path_stats.direct += 1;
+            }
+
+            if via_hidden.binary_search_by_key(&key(path), key).is_ok() {
+                path_stats.via_hidden += 1;
+            }
+        }
+    }
+
+    for (path, (sum, count)) in weight_sums {
+        if let Some(path_stats) = stats.paths.get_mut(&path) {
+            path_stats.mean_direct_weight = sum / count as f64;
+        }
+    }
+
+    stats
+}
+
+fn increment<K: std::hash::Hash + Eq>(map: &mut HashMap<K, usize>, key: K) {
+    *map.entry(key).or_insert(0) += 1;
+}
+
+/// Actions reachable from each neuron of a network, following hidden connections. An action
+/// neuron reaches itself. Each neuron is searched once, when first asked for
+struct Reachable {
+    outgoing: Vec<Vec<usize>>, // hidden edges by source neuron
+    actions: Vec<Option<Vec<Action>>>,
+    neuron_actions: Vec<Option<Action>>,
+}
+
+impl Reachable {
+    fn new(network: &Network) -> Self {
+        let mut outgoing = vec![vec![]; network.neuron_count()];
+        for (from, to, _) in network.hidden_edges() {
+            outgoing[from].push(to);
+        }
+
+        Reachable {
+            outgoing,
+            actions: vec![None; network.neuron_count()],
+            neuron_actions: (0..network.neuron_count()).map(|n| network.neuron_action(n)).collect(),
+        }
+    }
+
+    fn actions(&mut self, start: usize) -> &[Action] {
+        if self.actions[start].is_none() {
+            let mut visited = vec![false; self.outgoing.len()];
+            let mut stack = vec![start];
+            let mut res = vec![];
+
+            while let Some(neuron) = stack.pop() {
+                if visited[neuron] {
+                    continue;
+                }
+                visited[neuron] = true;
+
+                match self.neuron_actions[neuron] {
+                    Some(action) => res.push(action),
+                    None => stack.extend(&self.outgoing[neuron]),
+                }
+            }
+
+            self.actions[start] = Some(res);
+        }
+
+        self.actions[start].as_deref().unwrap_or(&[])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn direct_and_hidden_paths() {
+        let mut species = Species::new("test", 2);
+        species.sensor_neurons = vec![Sensor::Constant, Sensor::LocX];
+        species.action_neurons = vec![Action::MoveX, Action::MoveY];
+        species.hidden_neurons = 1;
+
+        // Inputs are 0..2 sensors, 2 the hidden neuron. Outputs are 0 hidden, 1..3 actions
+        let mut a = Individual::new();
+        a.genome = vec![
+            Gene { from_neuron: 0, to_neuron: 1, weight: 10_000 }, // Constant -> MoveX
+            Gene { from_neuron: 1, to_neuron: 0, weight: 10_000 }, // LocX -> hidden
+            Gene { from_neuron: 2, to_neuron: 2, weight: 10_000 }, // hidden -> MoveY
+        ];
+
+        let mut b = Individual::new();
+        b.genome = vec![
+            Gene { from_neuron: 0, to_neuron: 1, weight: 20_000 }, // Constant -> MoveX
+        ];
+
+        let stats = usage(&[a, b], &[species]);
+
+        assert_eq!(2, stats.population);
+        assert_eq!(Some(&2), stats.sensors_used.get(&Sensor::Constant));
+        assert_eq!(Some(&1), stats.sensors_used.get(&Sensor::LocX));
+        assert_eq!(Some(&2), stats.actions.get(&Action::MoveX));
+        assert_eq!(Some(&1), stats.actions.get(&Action::MoveY));
+
+        let direct = stats.paths[&(Sensor::Constant, Action::MoveX)];
+        assert_eq!(2, direct.direct);
+        assert_eq!(0, direct.via_hidden);
+        assert_relative_eq!(1.5, direct.mean_direct_weight);
+
+        let hidden = stats.paths[&(Sensor::LocX, Action::MoveY)];
+        assert_eq!(0, hidden.direct);
+        assert_eq!(1, hidden.via_hidden);
+        assert_eq!(1, hidden.any);
+
+        assert!(!stats.paths.contains_key(&(Sensor::Constant, Action::MoveY)));
+    }
+
+    #[test]
+    fn dead_end_hidden() {
+        let mut species = Species::new("test", 1);
+        species.sensor_neurons = vec![Sensor::Constant];
+        species.action_neurons = vec![Action::MoveX];
+        species.hidden_neurons = 1;
+
+        // Sensor into a hidden neuron that goes nowhere
+        let mut a = Individual::new();
+        a.genome = vec![Gene { from_neuron: 0, to_neuron: 0, weight: 10_000 }];
+
+        let stats = usage(&[a], &[species]);
+
+        assert_eq!(Some(&1), stats.sensors_connected.get(&Sensor::Constant));
+        assert_eq!(None, stats.sensors_used.get(&Sensor::Constant));
+        assert!(stats.paths.is_empty());
+    }
+}
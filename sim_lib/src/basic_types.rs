@@ -4,22 +4,162 @@ use crate::survival_criteria as sc;
 
 pub type Genome = Vec::<Gene>;
 
-#[derive(Debug, PartialEq, Clone, Copy)]
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
 pub struct Gene {
-    pub from_neuron: u8, // First all the inputs are setup, then all internal neuron
-    pub to_neuron: u8, // first come all internal neurons then action neurons
-    pub weight: i16, //  weight of connection
+    pub from_neuron: u16, // source type and index, see GeneLayout. Without a type bit first all the inputs, then all internal neurons
+    pub to_neuron: u16, // sink type and index. Without a type bit first all internal neurons then action neurons
+    pub weight: i32, //  weight of connection, sign extended from GeneLayout::weight_bits
 }
 
+/// Bits of a gene in the default layout
 pub static GENE_BITS : usize = 32;
 
+/// How a gene is packed into a string of bits. From the highest bits down: source type, source index,
+/// sink type, sink index and weight. A type field is 0 or 1 bit. With a type bit a source is a
+/// sensor (0) or hidden neuron (1), a sink a hidden (0) or action neuron (1), and the index picks
+/// within that group. Without it the index is taken modulo both groups together.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GeneLayout {
+    pub source_type_bits: u32,
+    pub source_index_bits: u32,
+    pub sink_type_bits: u32,
+    pub sink_index_bits: u32,
+    pub weight_bits: u32,
+}
+
+impl Default for GeneLayout {
+    /// The original 32 bit gene: 8 bit from_neuron, 8 bit to_neuron and 16 bit weight
+    fn default() -> Self {
+        GeneLayout {
+            source_type_bits: 0,
+            source_index_bits: 8,
+            sink_type_bits: 0,
+            sink_index_bits: 8,
+            weight_bits: 16,
+        }
+    }
+}
+
+impl GeneLayout {
+    /// 32 bit gene with type bits like biosim4: 1 + 7 bit source, 1 + 7 bit sink and 16 bit weight
+    pub fn typed() -> Self {
+        GeneLayout {
+            source_type_bits: 1,
+            source_index_bits: 7,
+            sink_type_bits: 1,
+            sink_index_bits: 7,
+            weight_bits: 16,
+        }
+    }
+
+    pub fn bits(&self) -> usize {
+        (self.source_bits() + self.sink_bits() + self.weight_bits) as usize
+    }
+
+    fn source_bits(&self) -> u32 {
+        self.source_type_bits + self.source_index_bits
+    }
+
+    fn sink_bits(&self) -> u32 {
+        self.sink_type_bits + self.sink_index_bits
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.source_type_bits > 1 || self.sink_type_bits > 1 {
+            return Err("type fields can be at most 1 bit".to_string());
+        }
+        if self.source_index_bits == 0 || self.sink_index_bits == 0 {
+            return Err("index fields need at least 1 bit".to_string());
+        }
+        if self.source_bits() > 16 || self.sink_bits() > 16 {
+            return Err("source and sink can be at most 16 bits".to_string());
+        }
+        if self.weight_bits == 0 || self.weight_bits > 32 {
+            return Err("weight must be 1 to 32 bits".to_string());
+        }
+        if self.bits() > 64 {
+            return Err(format!("gene is {} bits, at most 64 fit", self.bits()));
+        }
+        Ok(())
+    }
+
+    /// The gene as one string of bits, in the lowest bits() bits. Fields are truncated to their width
+    pub fn pack(&self, gene: &Gene) -> u64 {
+        let source = low_bits(gene.from_neuron as u64, self.source_bits());
+        let sink = low_bits(gene.to_neuron as u64, self.sink_bits());
+        let weight = low_bits(gene.weight as u32 as u64, self.weight_bits);
+
+        source << (self.sink_bits() + self.weight_bits) | sink << self.weight_bits | weight
+    }
+
+    /// Inverse of pack. Bits above bits() are ignored
+    pub fn unpack(&self, bits: u64) -> Gene {
+        let weight = low_bits(bits, self.weight_bits);
+        // sign extend the weight
+        let shift = 64 - self.weight_bits;
+        Gene {
+            from_neuron: low_bits(bits >> (self.sink_bits() + self.weight_bits), self.source_bits()) as u16,
+            to_neuron: low_bits(bits >> self.weight_bits, self.sink_bits()) as u16,
+            weight: ((weight << shift) as i64 >> shift) as i32,
+        }
+    }
+
+    /// pack for layouts of at most 32 bits
+    pub fn pack_u32(&self, gene: &Gene) -> u32 {
+        debug_assert!(self.bits() <= 32, "{} bit layout does not fit in u32", self.bits());
+        self.pack(gene) as u32
+    }
+
+    pub fn unpack_u32(&self, bits: u32) -> Gene {
+        self.unpack(bits as u64)
+    }
+
+    /// Index of the source neuron, with the sensors first and then the hidden neurons
+    pub fn source_index(&self, gene: &Gene, sensors: usize, hidden: usize) -> usize {
+        typed_index(gene.from_neuron, self.source_type_bits, self.source_index_bits, sensors, hidden)
+    }
+
+    /// Index of the sink neuron, with the hidden neurons first and then the actions
+    pub fn sink_index(&self, gene: &Gene, hidden: usize, actions: usize) -> usize {
+        typed_index(gene.to_neuron, self.sink_type_bits, self.sink_index_bits, hidden, actions)
+    }
+
+    /// Multiplier that brings a weight to the range of a 16 bit weight, so WEIGHT_SCALE works for every width
+    pub fn weight_factor(&self) -> f64 {
+        2f64.powi(16 - self.weight_bits as i32)
+    }
+}
+
+fn low_bits(value: u64, bits: u32) -> u64 {
+    if bits >= 64 { value } else { value & ((1 << bits) - 1) }
+}
+
+/// Index into two groups that follow each other. A set type bit picks the second group. When the
+/// picked group is empty the other one is used
+fn typed_index(field: u16, type_bits: u32, index_bits: u32, first: usize, second: usize) -> usize {
+    let field = field as usize;
+    if type_bits == 0 {
+        return field % (first + second);
+    }
+
+    let index = field & ((1 << index_bits) - 1);
+    let second_group = (field >> index_bits) & 1 == 1;
+
+    if (second_group && second > 0) || first == 0 {
+        first + index % second
+    }
+    else {
+        index % first
+    }
+}
+
 macro_rules! make_enum {
     (
         $name:ident $array:ident {
             $( $variant:ident, )*
         }
     ) => {
-        #[derive(Debug, PartialEq, Clone, Copy)]
+        #[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
         pub enum $name {
             $( $variant, )*
         }
@@ -34,6 +174,14 @@ pub fn all_sensors() -> Vec::<Sensor> {
     ALL_SENSORS.to_vec()
 }
 
+/// Only useful with food in the world. Not in default_sensors, so genomes decode as they did before these existed
+pub static RESOURCE_SENSORS: &[Sensor] = &[Sensor::Energy, Sensor::FoodGradientX, Sensor::FoodGradientY];
+
+/// All sensors except the RESOURCE_SENSORS
+pub fn default_sensors() -> Vec::<Sensor> {
+    ALL_SENSORS.iter().copied().filter(|s| !RESOURCE_SENSORS.contains(s)).collect()
+}
+
 make_enum! (Sensor ALL_SENSORS {
     // Location
     LocY,
@@ -45,6 +193,10 @@ make_enum! (Sensor ALL_SENSORS {
     BlockedForward,
     PheromoneDensity,
 
+    Energy,
+    FoodGradientX,
+    FoodGradientY,
+
     Random,
     Constant,
 
@@ -58,18 +210,36 @@ pub struct Individual {
     // brain not stored here, but on simulation
     pub grid_index: usize,
     pub index: usize,
-    pub forward: Dir
+    pub forward: Dir,
+    pub tracker: sc::Tracker,
+    pub energy: f32,
+    pub age: usize, // steps lived
+    pub species: usize, // index into the simulations species
+    pub cluster: usize, // genetic species, set by speciation
+    pub id: usize, // unique over the whole run, see lineage
+    pub parent: Option<usize>, // id of the parent
 }
 
 impl Individual {
 
     pub fn new() -> Self {
-        let mut rng = rand::thread_rng();
+        Self::with_rng(&mut rand::thread_rng())
+    }
+
+    /// Like new, with the start direction picked by rng
+    pub fn with_rng<R: Rng + ?Sized>(rng: &mut R) -> Self {
         Self {
             genome: vec![],
             grid_index: 0,
             forward: ALL_DIRS[rng.gen_range(0..ALL_DIRS.len())],
-            index: 0
+            index: 0,
+            tracker: Default::default(),
+            energy: 1.0,
+            age: 0,
+            species: 0,
+            cluster: 0,
+            id: 0,
+            parent: None,
         }
     }
 }
@@ -81,6 +251,14 @@ pub fn all_actions() -> Vec::<Action> {
     ALL_ACTIONS.to_vec()
 }
 
+/// Only useful with food in the world, not in default_actions. See RESOURCE_SENSORS
+pub static RESOURCE_ACTIONS: &[Action] = &[Action::Eat];
+
+/// All actions except the RESOURCE_ACTIONS
+pub fn default_actions() -> Vec::<Action> {
+    ALL_ACTIONS.iter().copied().filter(|a| !RESOURCE_ACTIONS.contains(a)).collect()
+}
+
 make_enum! (Action ALL_ACTIONS {
     MoveForward,
     MoveX,
@@ -88,6 +266,8 @@ make_enum! (Action ALL_ACTIONS {
 
     EmitPheromones,
 
+    Eat,
+
     SetOscPeriod,
 
     SetResponsivness,
@@ -95,6 +275,49 @@ make_enum! (Action ALL_ACTIONS {
 });
 
 
+// ACTIVATION FUNCTIONS
+make_enum! (ActivationFunction ALL_ACTIVATION_FUNCTIONS {
+    Tanh,
+    Sigmoid,
+    Relu,
+    Step,
+    Identity,
+    Sine,
+});
+
+impl ActivationFunction {
+    /// Output ranges: Tanh and Sine -1..1, Sigmoid 0..1, Step 0 or 1, Relu 0.. and Identity
+    /// unbounded. Sigmoid is 0.5 for input 0, so it works as a bias without any input.
+    pub fn apply(self, x: f64) -> f64 {
+        match self {
+            ActivationFunction::Tanh => f64::tanh(x),
+            ActivationFunction::Sigmoid => 1.0 / (1.0 + f64::exp(-x)),
+            ActivationFunction::Relu => f64::max(x, 0.0),
+            ActivationFunction::Step => if x > 0.0 { 1.0 } else { 0.0 },
+            ActivationFunction::Identity => x,
+            ActivationFunction::Sine => f64::sin(x),
+        }
+    }
+}
+
+/// Activation function of the hidden neurons. Action neurons always use tanh, since the
+/// actions expect a value in -1..1.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NeuronActivation {
+    /// The same function for all hidden neurons
+    Network(ActivationFunction),
+    /// The first hidden_neurons genes of the genome are neuron genes and add no connection.
+    /// Gene i picks the function of hidden neuron i, see gene_functions::neuron_gene_activation
+    Genome,
+}
+
+impl Default for NeuronActivation {
+    fn default() -> Self {
+        NeuronActivation::Network(ActivationFunction::Tanh)
+    }
+}
+
+
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub struct Coord {
     pub x: usize,
@@ -121,7 +344,7 @@ make_enum! (Dir ALL_DIRS {
 
 
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone)]
 pub struct Configuration {
     pub hidden_neurons: usize,
     pub generation_steps: usize,
@@ -129,7 +352,18 @@ pub struct Configuration {
     pub population_size: usize,
     pub genome_length: usize,
     pub criteria: sc::SurvivalCriteria,
-
+    // When set, criteria is replaced by the scheduled criteria at the start of each generation
+    pub criteria_schedule: Option<sc::CriteriaSchedule>,
+    pub resources: ResourceConfig,
+    pub mode: EvolutionMode,
+    pub speciation_threshold: f64, // max genome distance to join a cluster
+    pub hidden_evaluation: HiddenEvaluation,
+    pub activation: NeuronActivation,
+    pub gene_layout: GeneLayout,
+    // Drop lineage records without living descendants at the end of each generation
+    pub prune_lineage: bool,
+    // Measure analysis::usage at the end of every usage_interval-th generation, 0 never does
+    pub usage_interval: usize,
 }
 
 
@@ -137,6 +371,15 @@ impl Default for Configuration {
     fn default () -> Self {
         Configuration {
             criteria: sc::SurvivalCriteria::Border(0.020),
+            criteria_schedule: None,
+            resources: ResourceConfig::default(),
+            mode: EvolutionMode::Generational,
+            speciation_threshold: 0.3,
+            hidden_evaluation: HiddenEvaluation::GenomeOrder,
+            activation: NeuronActivation::default(),
+            gene_layout: GeneLayout::default(),
+            prune_lineage: false,
+            usage_interval: 0,
             genome_length: 24,
             population_size: 1000,
             hidden_neurons: 5,
@@ -148,6 +391,62 @@ impl Default for Configuration {
 
 
 
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EvolutionMode {
+    /// Every individual is replaced by offspring of the survivors after generation_steps
+    Generational,
+    /// Individuals die during the run and are replaced right away by offspring of a random
+    /// living individual, placed within birth_radius of the parent.
+    /// Individuals die when reaching max_age, when out of energy, or when not matching
+    /// the criteria on a step that is a multiple of criteria_interval (0 disables it).
+    /// Generations are still counted every generation_steps, for stats and schedules.
+    SteadyState {
+        max_age: usize,
+        criteria_interval: usize,
+        birth_radius: usize,
+    },
+}
+
+/// How connections starting from a hidden neuron are evaluated
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HiddenEvaluation {
+    /// In genome order on the values of this step, so chains depend on the gene order
+    GenomeOrder,
+    /// Hidden neurons in index order. Connections to a higher hidden neuron or an action are
+    /// applied once all inputs of their source are in. Connections to the same or a lower
+    /// hidden neuron are ignored.
+    FeedForward,
+    /// Like FeedForward, but connections to the same or a lower hidden neuron read the value
+    /// their source had at the end of the previous step, so neurons can hold state.
+    Recurrent,
+}
+
+/// Food and energy. The default has no food and free actions, so energy never changes.
+#[derive(Debug, Clone, Copy)]
+pub struct ResourceConfig {
+    pub food_tiles: f32, // fraction of the grid that grows food
+    pub food_regrow_rate: u8, // food added to each food tile per step
+    pub start_energy: f32,
+    pub move_cost: f32,
+    pub emit_cost: f32,
+    pub eat_amount: u8, // max food eaten by a single eat action
+    pub food_energy: f32, // energy gained per unit of food eaten
+}
+
+impl Default for ResourceConfig {
+    fn default () -> Self {
+        ResourceConfig {
+            food_tiles: 0.0,
+            food_regrow_rate: 1,
+            start_energy: 1.0,
+            move_cost: 0.0,
+            emit_cost: 0.0,
+            eat_amount: 10,
+            food_energy: 0.01,
+        }
+    }
+}
+
 
 #[cfg(test)]
 mod tests {
@@ -156,7 +455,82 @@ mod tests {
 
     #[test]
     fn gene_bit_size() {
-        assert_eq!(GENE_BITS, 8 * std::mem::size_of::<Gene>());
+        assert_eq!(GENE_BITS, GeneLayout::default().bits());
+        assert_eq!(GENE_BITS, GeneLayout::typed().bits());
+    }
+
+    #[test]
+    fn default_neurons() {
+        // The order genomes from before the resource neurons were added decode with
+        assert_eq!(
+            vec![Sensor::LocY, Sensor::LocX, Sensor::WorldBorderDistX, Sensor::WorldBorderDistY, Sensor::BlockedForward,
+                 Sensor::PheromoneDensity, Sensor::Random, Sensor::Constant],
+            default_sensors()
+        );
+        assert_eq!(
+            vec![Action::MoveForward, Action::MoveX, Action::MoveY, Action::EmitPheromones, Action::SetOscPeriod, Action::SetResponsivness],
+            default_actions()
+        );
+    }
+
+    #[test]
+    fn gene_layout_pack() {
+        let gene = Gene { from_neuron: 1, to_neuron: 2, weight: -1 };
+        assert_eq!(0x0102_FFFF, GeneLayout::default().pack_u32(&gene));
+        assert_eq!(gene, GeneLayout::default().unpack_u32(0x0102_FFFF));
+
+        // 64 bit gene with a wide weight
+        let wide = GeneLayout { source_type_bits: 1, source_index_bits: 15, sink_type_bits: 1, sink_index_bits: 15, weight_bits: 32 };
+        assert!(wide.validate().is_ok());
+        assert_eq!(64, wide.bits());
+        let gene = Gene { from_neuron: 0xABCD, to_neuron: 0x1234, weight: -70_000 };
+        assert_eq!(gene, wide.unpack(wide.pack(&gene)));
+        assert_eq!(1.0 / 65536.0, wide.weight_factor());
+
+        // Narrow fields truncate, and the weight is sign extended
+        let narrow = GeneLayout { source_type_bits: 0, source_index_bits: 4, sink_type_bits: 0, sink_index_bits: 4, weight_bits: 4 };
+        assert_eq!(12, narrow.bits());
+        let unpacked = narrow.unpack(narrow.pack(&Gene { from_neuron: 0x1F, to_neuron: 0x2, weight: -3 }));
+        assert_eq!(Gene { from_neuron: 0xF, to_neuron: 0x2, weight: -3 }, unpacked);
+
+        assert!(GeneLayout { source_type_bits: 2, ..GeneLayout::default() }.validate().is_err());
+        assert!(GeneLayout { weight_bits: 0, ..GeneLayout::default() }.validate().is_err());
+        assert!(GeneLayout { source_index_bits: 16, source_type_bits: 1, ..GeneLayout::default() }.validate().is_err());
+    }
+
+    #[test]
+    fn gene_layout_type_bits() {
+        let layout = GeneLayout::typed();
+        // 3 sensors, 2 hidden, 4 actions
+        let sensor = Gene { from_neuron: 4, to_neuron: 0x80 | 5, weight: 0 };
+        assert_eq!(1, layout.source_index(&sensor, 3, 2));
+        assert_eq!(2 + 1, layout.sink_index(&sensor, 2, 4));
+
+        let hidden = Gene { from_neuron: 0x80 | 3, to_neuron: 3, weight: 0 };
+        assert_eq!(3 + 1, layout.source_index(&hidden, 3, 2));
+        assert_eq!(1, layout.sink_index(&hidden, 2, 4));
+
+        // No hidden neurons, so both types pick from the other group
+        assert_eq!(1, layout.source_index(&hidden, 2, 0));
+        assert_eq!(3, layout.sink_index(&hidden, 0, 4));
+
+        // Without type bits it is modulo over both groups
+        assert_eq!(4, GeneLayout::default().source_index(&sensor, 3, 2));
+    }
+
+    #[test]
+    fn activation_functions() {
+        for f in ALL_ACTIVATION_FUNCTIONS {
+            assert!(f.apply(1.0) >= f.apply(-1.0), "{:?} should not decrease", f);
+        }
+
+        assert_relative_eq!(0.5, ActivationFunction::Sigmoid.apply(0.0));
+        assert_eq!(0.0, ActivationFunction::Relu.apply(-2.0));
+        assert_eq!(2.0, ActivationFunction::Relu.apply(2.0));
+        assert_eq!(0.0, ActivationFunction::Step.apply(0.0));
+        assert_eq!(1.0, ActivationFunction::Step.apply(0.1));
+        assert_eq!(-3.0, ActivationFunction::Identity.apply(-3.0));
+        assert_relative_eq!(1.0, ActivationFunction::Sine.apply(std::f64::consts::FRAC_PI_2));
     }
 
 }
@@ -1,11 +1,15 @@
 use crate::basic_types::*;
 use crate::grid::*;
+use crate::index_functions::*;
+use rand::seq::SliceRandom;
 
 
 #[derive(Debug, Clone)]
 pub struct World {
     pub grid: Grid,
     pub individuals: Vec::<Individual>,
+    pub resources: ResourceConfig,
+    pub random_seed: u64, // Sensor::Random readings of the current step, set by the simulation each step
 }
 
 impl World {
@@ -13,7 +17,9 @@ impl World {
 
         World {
             grid: Grid::new(size),
-            individuals: Vec::new()
+            individuals: Vec::new(),
+            resources: ResourceConfig::default(),
+            random_seed: 0,
         }
     }
 
@@ -65,6 +71,11 @@ impl World {
             return
         }
 
+        // Not enough energy to move
+        if self.individuals[indiv_index].energy < self.resources.move_cost {
+            return
+        }
+
         // calc new grid index
         let new_index = match dir {
             Dir::Right => old_index + 1,
@@ -76,6 +87,8 @@ impl World {
 
         // update the individuals grid index
         self.individuals[indiv_index].grid_index = new_index;
+        self.individuals[indiv_index].tracker.tiles_travelled += 1;
+        self.individuals[indiv_index].energy -= self.resources.move_cost;
 
         // update the grid
         self.grid.tiles[old_index].individual_index = None;
@@ -93,6 +106,50 @@ impl World {
         index
     }
 
+    /// Put indiv in the slot of the individual at index, removing the old one from the grid
+    pub fn replace_individual(&mut self, index: usize, mut indiv: Individual) {
+        let old_grid_index = self.individuals[index].grid_index;
+        self.grid.tiles[old_grid_index].individual_index = None;
+
+        if self.grid.tiles[indiv.grid_index].individual_index.is_some() {
+            panic!("\nReplacing indiv into grid index already occupied: Indiv:\n '{0:#?}'", indiv);
+        }
+
+        indiv.index = index;
+        self.grid.tiles[indiv.grid_index].individual_index = Some(index);
+        self.individuals[index] = indiv;
+    }
+
+    /// Find a random empty tile within radius of grid_index
+    pub fn find_empty_near<R: rand::Rng>(&self, grid_index: usize, radius: usize, rng: &mut R) -> Option<usize> {
+        let center = index_to_coord(grid_index, self.grid.size);
+
+        let mut candidates = vec![];
+        for x in center.x.saturating_sub(radius)..=usize::min(center.x + radius, self.grid.size.x - 1) {
+            for y in center.y.saturating_sub(radius)..=usize::min(center.y + radius, self.grid.size.y - 1) {
+                let index = coord_to_index(Coord { x, y }, self.grid.size);
+                if self.grid.tiles[index].is_empty() {
+                    candidates.push(index);
+                }
+            }
+        }
+
+        candidates.choose(rng).copied()
+    }
+
+    /// Replace the dead individual at index with indiv, placed on a random empty tile within radius of near.
+    /// The dead one's tile counts as empty, and is used when there is no near or nothing near is free
+    pub fn respawn<R: rand::Rng>(&mut self, dead: usize, mut indiv: Individual, near: Option<usize>, radius: usize, rng: &mut R) {
+        let dead_grid_index = self.individuals[dead].grid_index;
+        self.grid.tiles[dead_grid_index].individual_index = None;
+
+        indiv.grid_index = near
+            .and_then(|grid_index| self.find_empty_near(grid_index, radius, rng))
+            .unwrap_or(dead_grid_index);
+
+        self.replace_individual(dead, indiv);
+    }
+
     pub fn reset(&mut self, indivs: Vec::<Individual>) {
         // clear grid
         self.grid.clear();
@@ -100,7 +157,7 @@ impl World {
 
         // Update world with individual pos
         for indiv in &mut self.individuals {
-            if self.grid.tiles[indiv.grid_index].individual_index != None {
+            if self.grid.tiles[indiv.grid_index].individual_index.is_some() {
                 panic!("\nInsertering indiv into grid index already occupied: Indiv:\n '{0:#?}'", indiv);
             }
 
@@ -108,3 +165,48 @@ impl World {
         }
     }
 }
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+
+    fn world_with(grid_indexes: &[usize]) -> World {
+        let mut world = World::new(Coord {x: 8, y: 8});
+        for &grid_index in grid_indexes {
+            let mut indiv = Individual::new();
+            indiv.grid_index = grid_index;
+            world.add_individual(indiv);
+        }
+        world
+    }
+
+    #[test]
+    fn respawn() {
+        let mut rng = rand::rngs::StdRng::seed_from_u64(0);
+        let mut world = world_with(&[0, 1, 63]);
+
+        // tile 1 is free after the dead one is removed, and is the only free tile next to 0
+        world.grid.tiles[8].individual_index = Some(2);
+        world.grid.tiles[9].individual_index = Some(2);
+        world.respawn(1, Individual::new(), Some(0), 1, &mut rng);
+        assert_eq!(1, world.individuals[1].grid_index);
+        assert_eq!(Some(1), world.grid.tiles[1].individual_index);
+
+        // without a place to spawn near, the dead one's tile is used
+        world.respawn(2, Individual::new(), None, 1, &mut rng);
+        assert_eq!(63, world.individuals[2].grid_index);
+        assert_eq!(Some(2), world.grid.tiles[63].individual_index);
+
+        // placed near, the dead one's tile is freed
+        world.respawn(2, Individual::new(), Some(0), 3, &mut rng);
+        let grid_index = world.individuals[2].grid_index;
+        assert_ne!(63, grid_index);
+        assert!(world.grid.tiles[63].is_empty());
+        assert_eq!(Some(2), world.grid.tiles[grid_index].individual_index);
+
+        let occupied = world.grid.tiles.iter().filter(|tile| !tile.is_empty()).count();
+        assert_eq!(5, occupied);
+    }
+}
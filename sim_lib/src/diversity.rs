@@ -0,0 +1,196 @@
+use std::collections::HashMap;
+
+use crate::basic_types::*;
+use crate::species::Species;
+
+/// What a gene connects, decoded the same way as the network does
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ConnectionType {
+    SensorToHidden(usize),
+    SensorToAction(usize, usize),
+    HiddenToHidden,
+    HiddenToAction(usize),
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct DiversityStats {
+    pub distinct_genomes: usize,
+    pub mean_hamming_distance: f64, // differing bits between two genomes, averaged over all pairs
+    pub connection_entropy: f64, // Shannon entropy in bits of the connection types of all genes
+    pub top_genomes: Vec<(Genome, usize)>, // most frequent genomes with their count
+}
+
+pub fn measure(individuals: &[Individual], species: &[Species], top_n: usize) -> DiversityStats {
+    DiversityStats {
+        distinct_genomes: genome_counts(individuals).len(),
+        mean_hamming_distance: mean_hamming_distance(individuals, species),
+        connection_entropy: connection_entropy(individuals, species),
+        top_genomes: top_genomes(individuals, top_n),
+    }
+}
+
+fn genome_counts(individuals: &[Individual]) -> HashMap<&Genome, usize> {
+    let mut counts = HashMap::new();
+
+    for indiv in individuals {
+        *counts.entry(&indiv.genome).or_insert(0) += 1;
+    }
+
+    counts
+}
+
+pub fn top_genomes(individuals: &[Individual], n: usize) -> Vec<(Genome, usize)> {
+    let mut counts: Vec<(&Genome, usize)> = genome_counts(individuals).into_iter().collect();
+
+    // Most frequent first, ties broken on the genome so the result is stable
+    counts.sort_by(|a, b| {
+        b.1.cmp(&a.1).then_with(|| {
+            let a_genes = a.0.iter().map(|g| (g.from_neuron, g.to_neuron, g.weight));
+            let b_genes = b.0.iter().map(|g| (g.from_neuron, g.to_neuron, g.weight));
+            a_genes.cmp(b_genes)
+        })
+    });
+
+    counts.into_iter().take(n).map(|(g, c)| (g.clone(), c)).collect()
+}
+
+/// Mean number of differing bits over all pairs of genomes, comparing gene by gene.
+/// Only the length of the shortest genome is compared.
+/// Genes are packed with the gene layout of their species.
+/// Counts ones per bit position, so it is linear in the population size.
+pub fn mean_hamming_distance(individuals: &[Individual], species: &[Species]) -> f64 {
+    let n = individuals.len();
+    if n < 2 {
+        return 0.0;
+    }
+
+    let len = individuals.iter().map(|i| i.genome.len()).min().unwrap_or(0);
+
+    // Room for the widest layout, so genes of every species fit
+    let gene_bits = species.iter().map(|s| s.gene_layout.bits()).max().unwrap_or(GENE_BITS);
+    let mut ones = vec![0usize; len * gene_bits];
+
+    for indiv in individuals {
+        let layout = &species[indiv.species].gene_layout;
+        for (gene_index, gene) in indiv.genome.iter().take(len).enumerate() {
+            let bits = layout.pack(gene);
+            for bit in 0..layout.bits() {
+                if bits & (1 << bit) != 0 {
+                    ones[gene_index * gene_bits + bit] += 1;
+                }
+            }
+        }
+    }
+
+    // Each bit position contributes ones * zeros differing pairs
+    let differing: f64 = ones.iter().map(|&o| (o * (n - o)) as f64).sum();
+    let pairs = (n * (n - 1) / 2) as f64;
+
+    differing / pairs
+}
+
+/// What a connection gene connects. See Species::connection_genes for which genes those are
+pub fn connection_type(gene: &Gene, species: &Species) -> ConnectionType {
+    let inputs = species.sensor_neurons.len();
+    let hidden = species.hidden_neurons;
+
+    let from = species.gene_layout.source_index(gene, inputs, hidden);
+    let to = species.gene_layout.sink_index(gene, hidden, species.action_neurons.len());
+
+    match (from < inputs, to < hidden) {
+        (true, true) => ConnectionType::SensorToHidden(from),
+        (true, false) => ConnectionType::SensorToAction(from, to - hidden),
+        (false, true) => ConnectionType::HiddenToHidden,
+        (false, false) => ConnectionType::HiddenToAction(to - hidden),
+    }
+}
+
+/// Shannon entropy of the connection types over all connection genes in the population.
+/// 0 when every gene makes the same kind of connection.
+pub fn connection_entropy(individuals: &[Individual], species: &[Species]) -> f64 {
+    let mut counts: HashMap<(usize, ConnectionType), usize> = HashMap::new();
+    let mut total = 0;
+
+    for indiv in individuals {
+        let indiv_species = &species[indiv.species];
+        for gene in indiv_species.connection_genes(&indiv.genome) {
+            let con = connection_type(gene, indiv_species);
+            *counts.entry((indiv.species, con)).or_insert(0) += 1;
+            total += 1;
+        }
+    }
+
+    counts
+        .values()
+        .map(|&c| {
+            let p = c as f64 / total as f64;
+            -p * p.log2()
+        })
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn indiv(genome: Genome) -> Individual {
+        let mut indiv = Individual::new();
+        indiv.genome = genome;
+        indiv
+    }
+
+    fn gene(from_neuron: u16, to_neuron: u16, weight: i32) -> Gene {
+        Gene { from_neuron, to_neuron, weight }
+    }
+
+    #[test]
+    fn hamming() {
+        let a = indiv(vec![gene(0, 0, 0), gene(0, 0, 0)]);
+        let b = indiv(vec![gene(1, 0, 0), gene(0, 0, 3)]);
+        let species = vec![Species::new("test", 3)];
+
+        assert_eq!(0.0, mean_hamming_distance(&[a.clone(), a.clone()], &species));
+        assert_eq!(3.0, mean_hamming_distance(&[a.clone(), b.clone()], &species));
+
+        // pairs: a-a 0, a-b 3, a-b 3
+        assert_eq!(2.0, mean_hamming_distance(&[a.clone(), a, b], &species));
+    }
+
+    #[test]
+    fn top_and_distinct() {
+        let a = indiv(vec![gene(0, 0, 0)]);
+        let b = indiv(vec![gene(1, 0, 0)]);
+
+        let indivs = vec![a.clone(), b.clone(), b.clone()];
+        let species = vec![Species::new("test", 3)];
+
+        let stats = measure(&indivs, &species, 1);
+
+        assert_eq!(2, stats.distinct_genomes);
+        assert_eq!(vec![(b.genome, 2)], stats.top_genomes);
+    }
+
+    #[test]
+    fn entropy() {
+        let mut species = Species::new("test", 2);
+        species.sensor_neurons = vec![Sensor::Constant, Sensor::Random];
+        species.action_neurons = vec![Action::MoveX];
+        species.hidden_neurons = 0;
+        let species = vec![species];
+
+        let same = vec![indiv(vec![gene(0, 0, 0)]), indiv(vec![gene(2, 0, 5)])];
+        assert_eq!(0.0, connection_entropy(&same, &species));
+
+        // Two equally common types is one bit
+        let two = vec![indiv(vec![gene(0, 0, 0)]), indiv(vec![gene(1, 0, 0)])];
+        assert_eq!(1.0, connection_entropy(&two, &species));
+
+        // Neuron genes are not connections
+        let mut genome_activation = species[0].clone();
+        genome_activation.hidden_neurons = 1;
+        genome_activation.activation = NeuronActivation::Genome;
+        let neuron_gene = vec![indiv(vec![gene(0, 0, 0), gene(2, 1, 0)]), indiv(vec![gene(1, 0, 0), gene(2, 1, 0)])];
+        assert_eq!(0.0, connection_entropy(&neuron_gene, &[genome_activation]));
+    }
+}
@@ -1,9 +1,9 @@
-use crate::basic_types::{Gene, Genome, GENE_BITS};
+use crate::basic_types::{ActivationFunction, Gene, GeneLayout, Genome, ALL_ACTIVATION_FUNCTIONS};
 
-#[allow(type_alias_bounds)]
-pub type GenomeFunc<R: rand::Rng> = fn(rng: &mut R, genome_len: usize) -> Genome;
+/// Genomes of a first generation, see Simulation::initialize_first_generation
+pub type GenomeFunc = fn(rng: &mut dyn rand::RngCore, genome_len: usize) -> Genome;
 
-pub fn fixed_genome<R: rand::Rng>(_rng: &mut R, genome_len: usize, from_neuron: u8, to_neuron: u8) -> Genome {
+pub fn fixed_genome<R: rand::Rng + ?Sized>(_rng: &mut R, genome_len: usize, from_neuron: u16, to_neuron: u16) -> Genome {
 
     let mut res = vec![];
     for _ in 0..genome_len {
@@ -17,6 +17,17 @@ pub fn fixed_genome<R: rand::Rng>(_rng: &mut R, genome_len: usize, from_neuron:
 
 }
 
+/// The gene as one string of bits in the default layout. from_neuron is the highest byte and weight
+/// the lowest 16 bits, see GeneLayout::pack for other layouts
+pub fn gene_to_bits(gene: &Gene) -> u32 {
+    GeneLayout::default().pack_u32(gene)
+}
+
+/// Activation function of a neuron gene, picked by its weight. See NeuronActivation::Genome
+pub fn neuron_gene_activation(gene: &Gene) -> ActivationFunction {
+    ALL_ACTIVATION_FUNCTIONS[gene.weight as u32 as usize % ALL_ACTIVATION_FUNCTIONS.len()]
+}
+
 pub fn genome_to_rgb(genome: &Genome) -> (u8, u8, u8) {
 
     let len = genome.len() as f32;
@@ -32,19 +43,93 @@ pub fn genome_to_rgb(genome: &Genome) -> (u8, u8, u8) {
     ((rgb[0]/ len) as u8, (rgb[1]/ len) as u8, (rgb[2]/ len) as u8)
 }
 
+/// Canonical text form of a genome. Each gene is packed with the layout and written as hex digits,
+/// 8 for 32 bit genes, and genes are separated by a space
+pub fn genome_to_hex(genome: &Genome, layout: &GeneLayout) -> String {
+    let digits = hex_digits(layout);
+    genome
+        .iter()
+        .map(|gene| format!("{:0width$x}", layout.pack(gene), width = digits))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Parse the text form of genome_to_hex. Genes can be separated by any whitespace, so wrapped lines work
+pub fn genome_from_hex(text: &str, layout: &GeneLayout) -> Result<Genome, GenomeParseError> {
+    let digits = hex_digits(layout);
+    let mut genome = vec![];
+
+    for (gene, word) in text.split_whitespace().enumerate() {
+        if !word.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(GenomeParseError::InvalidDigit { gene, text: word.to_string() });
+        }
+
+        if word.len() != digits {
+            return Err(GenomeParseError::WrongLength { gene, expected: digits, found: word.len() });
+        }
+
+        // all hex digits and at most 16 of them, so it fits
+        let bits = u64::from_str_radix(word, 16).unwrap();
+        if layout.bits() < 64 && bits >> layout.bits() != 0 {
+            return Err(GenomeParseError::TooWide { gene, bits: layout.bits() });
+        }
+
+        genome.push(layout.unpack(bits));
+    }
+
+    if genome.is_empty() {
+        return Err(GenomeParseError::Empty);
+    }
+
+    Ok(genome)
+}
+
+fn hex_digits(layout: &GeneLayout) -> usize {
+    layout.bits().div_ceil(4)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GenomeParseError {
+    /// No genes in the text
+    Empty,
+    /// A character that is not a hex digit
+    InvalidDigit { gene: usize, text: String },
+    /// Not the number of digits the layout needs
+    WrongLength { gene: usize, expected: usize, found: usize },
+    /// Bits set above the width of the layout
+    TooWide { gene: usize, bits: usize },
+}
+
+impl std::fmt::Display for GenomeParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            GenomeParseError::Empty => write!(f, "genome has no genes"),
+            GenomeParseError::InvalidDigit { gene, text } => write!(f, "gene {} '{}' is not hex", gene, text),
+            GenomeParseError::WrongLength { gene, expected, found } => {
+                write!(f, "gene {} has {} digits, expected {}", gene, found, expected)
+            }
+            GenomeParseError::TooWide { gene, bits } => write!(f, "gene {} does not fit in {} bits", gene, bits),
+        }
+    }
+}
+
+impl std::error::Error for GenomeParseError {}
+
+/// Random genome in the default layout
 pub fn random_genome<R: rand::Rng>(rng: &mut R, genome_len: usize) -> Genome {
+    random_layout_genome(rng, genome_len, &GeneLayout::default())
+}
+
+/// Random genome where every bit of the layout is random
+pub fn random_layout_genome<R: rand::Rng + ?Sized>(rng: &mut R, genome_len: usize, layout: &GeneLayout) -> Genome {
     let mut res = vec![];
     for _ in 0..genome_len {
-        res.push(Gene {
-            from_neuron: rng.gen(),
-            to_neuron: rng.gen(),
-            weight: rng.gen()
-        })
+        res.push(layout.unpack(rng.gen()))
     }
     res
 }
 
-pub fn mutate_genome<R: rand::Rng>(rng: &mut R, mut_rate: f32, genome: &mut Genome) {
+pub fn mutate_genome<R: rand::Rng + ?Sized>(rng: &mut R, mut_rate: f32, genome: &mut Genome, layout: &GeneLayout) {
 
     let len = genome.len();
 
@@ -55,8 +140,8 @@ pub fn mutate_genome<R: rand::Rng>(rng: &mut R, mut_rate: f32, genome: &mut Geno
             match rng.gen::<f32>() {
                 // bit flip
                 x if x <= 1.0 => {
-                    let bit_index = rng.gen_range(0..32 * len);
-                    bit_flip(&mut genome[i], bit_index);
+                    let bit_index = rng.gen_range(0..layout.bits() * len);
+                    bit_flip(layout, &mut genome[i], bit_index);
                 },
                 _ => {}
             }
@@ -67,22 +152,10 @@ pub fn mutate_genome<R: rand::Rng>(rng: &mut R, mut_rate: f32, genome: &mut Geno
 
 
 // Flip bit at index bit_index in the genome. Seeing the whole genome a one long string of bits
-// bit_index should be lower than genome.len() * gene.length (32bit atm)
-fn bit_flip(gene: &mut Gene, mut bit_index: usize) {
-
-    bit_index = bit_index % GENE_BITS;
-
-    match bit_index {
-        _x if _x < 16 => {// weight
-            gene.weight ^= 1 << bit_index
-        },
-        _x if _x < 24 => { // to_neuron
-            gene.to_neuron ^= 1 << (bit_index - 16)
-        },
-        _x  => { // from_neuron
-            gene.from_neuron ^= 1 << (bit_index - 24)
-        }
-    }
+// bit_index should be lower than genome.len() * layout.bits()
+fn bit_flip(layout: &GeneLayout, gene: &mut Gene, bit_index: usize) {
+    let bit_index = bit_index % layout.bits();
+    *gene = layout.unpack(layout.pack(gene) ^ 1 << bit_index);
 }
 
 
@@ -96,12 +169,13 @@ mod tests {
 
         let mut rng = rand::thread_rng();
         let gene = fixed_genome(&mut rng, 1, 0, 0)[0];
+        let layout = GeneLayout::default();
 
         let mut mut_gene = gene.clone();
 
         // FLIPPING FROM NEURON
         let before = mut_gene.from_neuron;
-        bit_flip(&mut mut_gene, 31);
+        bit_flip(&layout, &mut mut_gene, 31);
 
         assert_ne!(before, mut_gene.from_neuron);
         assert_eq!(128, mut_gene.from_neuron);
@@ -109,7 +183,7 @@ mod tests {
         // FLIPPING TO NEURON
 
         let before = mut_gene.to_neuron;
-        bit_flip(&mut mut_gene, 17);
+        bit_flip(&layout, &mut mut_gene, 17);
 
 
         assert_ne!(before, mut_gene.to_neuron);
@@ -117,11 +191,98 @@ mod tests {
 
         // FLIPPING WEIGHT
         let before = mut_gene.weight;
-        bit_flip(&mut mut_gene, 2);
+        bit_flip(&layout, &mut mut_gene, 2);
 
         assert_ne!(before, mut_gene.weight);
         assert_eq!(10004, mut_gene.weight);
 
     }
 
+    #[test]
+    fn gene_bits() {
+        let gene = Gene { from_neuron: 1, to_neuron: 2, weight: -1 };
+        assert_eq!(0x0102_FFFF, gene_to_bits(&gene));
+
+        // Flipping bit i flips bit i of the bits
+        let mut flipped = gene;
+        bit_flip(&GeneLayout::default(), &mut flipped, 20);
+        assert_eq!(gene_to_bits(&gene) ^ (1 << 20), gene_to_bits(&flipped));
+    }
+
+    #[test]
+    fn hex_round_trip() {
+        let genome = vec![
+            Gene { from_neuron: 1, to_neuron: 2, weight: -1 },
+            Gene { from_neuron: 0xAB, to_neuron: 0, weight: 10_000 },
+        ];
+        let layout = GeneLayout::default();
+
+        let text = genome_to_hex(&genome, &layout);
+        assert_eq!("0102ffff ab002710", text);
+        assert_eq!(Ok(genome.clone()), genome_from_hex(&text, &layout));
+        assert_eq!(Ok(genome), genome_from_hex(" 0102FFFF\n\tab002710\n", &layout));
+
+        // 12 bit genes take 3 digits
+        let narrow = GeneLayout { source_type_bits: 0, source_index_bits: 4, sink_type_bits: 0, sink_index_bits: 4, weight_bits: 4 };
+        let mut rng = rand::thread_rng();
+        let genome = random_layout_genome(&mut rng, 20, &narrow);
+        let text = genome_to_hex(&genome, &narrow);
+        assert_eq!(20 * 4 - 1, text.len());
+        assert_eq!(Ok(genome), genome_from_hex(&text, &narrow));
+    }
+
+    #[test]
+    fn hex_parse_errors() {
+        let layout = GeneLayout::default();
+
+        assert_eq!(Err(GenomeParseError::Empty), genome_from_hex("  \n", &layout));
+        assert_eq!(
+            Err(GenomeParseError::InvalidDigit { gene: 1, text: "0102fffg".to_string() }),
+            genome_from_hex("0102ffff 0102fffg", &layout)
+        );
+        assert_eq!(
+            Err(GenomeParseError::InvalidDigit { gene: 0, text: "+102ffff".to_string() }),
+            genome_from_hex("+102ffff", &layout)
+        );
+        assert_eq!(
+            Err(GenomeParseError::WrongLength { gene: 0, expected: 8, found: 7 }),
+            genome_from_hex("102ffff", &layout)
+        );
+
+        let narrow = GeneLayout { weight_bits: 14, ..layout };
+        assert_eq!(Err(GenomeParseError::TooWide { gene: 0, bits: 30 }), genome_from_hex("4102ffff", &narrow));
+        assert_eq!("gene 0 does not fit in 30 bits", GenomeParseError::TooWide { gene: 0, bits: 30 }.to_string());
+    }
+
+    #[test]
+    fn bit_flip_layout() {
+        // 1 + 11 bit source, 1 + 11 bit sink and 24 bit weight
+        let layout = GeneLayout { source_type_bits: 1, source_index_bits: 11, sink_type_bits: 1, sink_index_bits: 11, weight_bits: 24 };
+        let gene = Gene { from_neuron: 0, to_neuron: 0, weight: 0 };
+
+        for bit in 0..layout.bits() {
+            let mut flipped = gene;
+            bit_flip(&layout, &mut flipped, bit);
+            assert_eq!(1 << bit, layout.pack(&flipped));
+        }
+
+        // The highest weight bit is the sign, the type bits are the highest of source and sink
+        let mut flipped = gene;
+        bit_flip(&layout, &mut flipped, 23);
+        assert_eq!(-(1 << 23), flipped.weight);
+
+        let mut flipped = gene;
+        bit_flip(&layout, &mut flipped, 35);
+        assert_eq!(1 << 11, flipped.to_neuron);
+
+        let mut flipped = gene;
+        bit_flip(&layout, &mut flipped, 47);
+        assert_eq!(1 << 11, flipped.from_neuron);
+
+        let mut rng = rand::thread_rng();
+        for gene in random_layout_genome(&mut rng, 100, &layout) {
+            assert_eq!(gene, layout.unpack(layout.pack(&gene)));
+            assert!(layout.pack(&gene) < 1 << layout.bits());
+        }
+    }
 }
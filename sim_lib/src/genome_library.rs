@@ -0,0 +1,117 @@
+use std::path::Path;
+
+use crate::basic_types::*;
+use crate::gene_functions::{genome_from_hex, genome_to_hex, GenomeParseError};
+
+/// Saved genomes, e.g. the survivors of a previous run. As text it is one genome per line in the
+/// form of genome_to_hex. Empty lines and lines starting with '#' are skipped.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct GenomeLibrary {
+    pub genomes: Vec<Genome>,
+}
+
+/// How the first generation is made from a library, see SimulationBuilder::genome_library
+#[derive(Debug, Clone)]
+pub struct LibrarySeeding {
+    pub library: GenomeLibrary,
+    pub random_ratio: f32, // share of individuals that get a random genome instead, 0..1
+    pub mutation_rate: f32, // mutation rate the mutation hook gets for genomes taken from the library
+}
+
+impl GenomeLibrary {
+    pub fn parse(text: &str, layout: &GeneLayout) -> Result<Self, LibraryError> {
+        let mut genomes = vec![];
+
+        for (line, content) in text.lines().enumerate() {
+            let content = content.trim();
+            if content.is_empty() || content.starts_with('#') {
+                continue;
+            }
+
+            let genome = genome_from_hex(content, layout).map_err(|error| LibraryError::Parse { line: line + 1, error })?;
+            genomes.push(genome);
+        }
+
+        if genomes.is_empty() {
+            return Err(LibraryError::Empty);
+        }
+
+        Ok(GenomeLibrary { genomes })
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P, layout: &GeneLayout) -> Result<Self, LibraryError> {
+        let text = std::fs::read_to_string(path).map_err(LibraryError::Io)?;
+        Self::parse(&text, layout)
+    }
+
+    pub fn to_text(&self, layout: &GeneLayout) -> String {
+        self.genomes.iter().map(|g| genome_to_hex(g, layout) + "\n").collect()
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P, layout: &GeneLayout) -> Result<(), LibraryError> {
+        std::fs::write(path, self.to_text(layout)).map_err(LibraryError::Io)
+    }
+}
+
+#[derive(Debug)]
+pub enum LibraryError {
+    Io(std::io::Error),
+    /// line is 1 based
+    Parse { line: usize, error: GenomeParseError },
+    /// No genomes in the library
+    Empty,
+}
+
+impl std::fmt::Display for LibraryError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            LibraryError::Io(e) => write!(f, "could not read genome library: {}", e),
+            LibraryError::Parse { line, error } => write!(f, "line {}: {}", line, error),
+            LibraryError::Empty => write!(f, "genome library has no genomes"),
+        }
+    }
+}
+
+impl std::error::Error for LibraryError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            LibraryError::Io(e) => Some(e),
+            LibraryError::Parse { error, .. } => Some(error),
+            LibraryError::Empty => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn parse_and_save() {
+        let layout = GeneLayout::default();
+        let text = "# survivors of generation 10\n\n0102ffff ab002710\n  00000001\n";
+
+        let library = GenomeLibrary::parse(text, &layout).unwrap();
+        assert_eq!(2, library.genomes.len());
+        assert_eq!(vec![Gene { from_neuron: 0, to_neuron: 0, weight: 1 }], library.genomes[1]);
+        assert_eq!("0102ffff ab002710\n00000001\n", library.to_text(&layout));
+
+        let path = std::env::temp_dir().join(format!("genome_library_{}.txt", std::process::id()));
+        library.save(&path, &layout).unwrap();
+        assert_eq!(library, GenomeLibrary::load(&path, &layout).unwrap());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn errors() {
+        let layout = GeneLayout::default();
+
+        assert!(matches!(GenomeLibrary::parse("# nothing\n", &layout), Err(LibraryError::Empty)));
+        assert!(matches!(
+            GenomeLibrary::parse("0102ffff\n0102ff\n", &layout),
+            Err(LibraryError::Parse { line: 2, error: GenomeParseError::WrongLength { gene: 0, expected: 8, found: 6 } })
+        ));
+        assert!(matches!(GenomeLibrary::load("/no/such/library.txt", &layout), Err(LibraryError::Io(_))));
+    }
+}
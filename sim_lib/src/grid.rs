@@ -1,9 +1,12 @@
 use crate::basic_types::Coord;
+use rand::seq::SliceRandom;
 
 #[derive(Debug, Default, Clone)]
 pub struct GridTile {
     pub individual_index: Option<usize>,
     pub pheromone_level: u8,
+    pub food_source: bool,
+    pub food: u8,
 }
 
 impl GridTile {
@@ -31,9 +34,43 @@ impl Grid {
         for tile in &mut self.tiles {
             tile.individual_index = None;
             tile.pheromone_level = 0;
+            tile.food = if tile.food_source { u8::MAX } else { 0 };
         }
     }
 
+    /// Turn a random fraction of the tiles into full food tiles
+    pub fn place_food<R: rand::Rng>(&mut self, rng: &mut R, fraction: f32) {
+        let mut indexes: Vec<usize> = (0..self.tiles.len()).collect();
+        indexes.shuffle(rng);
+
+        let count = (self.tiles.len() as f32 * fraction) as usize;
+
+        for tile in &mut self.tiles {
+            tile.food_source = false;
+            tile.food = 0;
+        }
+
+        for &index in indexes.iter().take(count) {
+            self.tiles[index].food_source = true;
+            self.tiles[index].food = u8::MAX;
+        }
+    }
+
+    pub fn regrow_food(&mut self, rate: u8) {
+        for tile in &mut self.tiles {
+            if tile.food_source {
+                tile.food = tile.food.saturating_add(rate);
+            }
+        }
+    }
+
+    /// Remove up to amount food from the tile. Returns how much was eaten
+    pub fn eat_food(&mut self, index: usize, amount: u8) -> u8 {
+        let eaten = u8::min(self.tiles[index].food, amount);
+        self.tiles[index].food -= eaten;
+        eaten
+    }
+
     pub fn increment_pheromone(&mut self, index: usize, inc: u8) {
         self.tiles[index].pheromone_level = self.tiles[index].pheromone_level.saturating_add(inc);
     }
@@ -83,4 +120,32 @@ mod tests {
 
         assert_eq!(0, grid.tiles[dec_index].pheromone_level);
     }
+
+    #[test]
+    fn food() {
+        let mut grid = Grid::new(Coord { x: 10, y: 10 });
+        let mut rng = rand::thread_rng();
+
+        grid.place_food(&mut rng, 0.2);
+
+        let food_tiles: Vec<usize> = (0..100).filter(|&i| grid.tiles[i].food_source).collect();
+        assert_eq!(20, food_tiles.len());
+
+        let index = food_tiles[0];
+        assert_eq!(255, grid.tiles[index].food);
+
+        assert_eq!(200, grid.eat_food(index, 200));
+        assert_eq!(55, grid.eat_food(index, 200));
+        assert_eq!(0, grid.eat_food(index, 200));
+
+        grid.regrow_food(5);
+        assert_eq!(5, grid.tiles[index].food);
+
+        // Non food tiles do not grow
+        let empty = (0..100).find(|&i| !grid.tiles[i].food_source).unwrap();
+        assert_eq!(0, grid.tiles[empty].food);
+
+        grid.clear();
+        assert_eq!(255, grid.tiles[index].food);
+    }
 }
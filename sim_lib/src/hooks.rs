@@ -0,0 +1,117 @@
+use rand::seq::SliceRandom;
+use rand::{Rng, RngCore};
+
+use crate::basic_types::*;
+use crate::combined_types::*;
+use crate::gene_functions;
+use crate::species::Species;
+
+// Hooks are set with the SimulationBuilder. Each one is also implemented for closures with the same
+// arguments, so they can capture settings. The rng is the one of the simulation, any Rng method works on it.
+
+/// Makes the genomes of the first generation, and of species that died out
+pub trait GenomeHook: Send + Sync {
+    fn create(&self, rng: &mut dyn RngCore, species: &Species, genome_len: usize) -> Genome;
+}
+
+/// Sets the grid_index of the individuals of a new generation. Every individual needs its own tile
+pub trait PlacementHook: Send + Sync {
+    fn place(&self, world: &World, indivs: &mut [Individual], rng: &mut dyn RngCore);
+
+    /// Tiles of a world of this size it can place individuals on, checked against the population size
+    fn capacity(&self, size: Coord) -> usize {
+        size.x * size.y
+    }
+}
+
+/// Mutates an offspring genome, copied from its parent
+pub trait MutationHook: Send + Sync {
+    fn mutate(&self, rng: &mut dyn RngCore, species: &Species, genome: &mut Genome);
+}
+
+/// Picks the parent of an offspring. candidates are indexes into world.individuals, never empty.
+/// They are the survivors of the species, or its living members in steady state mode
+pub trait SelectionHook: Send + Sync {
+    fn select(&self, rng: &mut dyn RngCore, world: &World, candidates: &[usize]) -> usize;
+}
+
+impl<F> GenomeHook for F
+where
+    F: Fn(&mut dyn RngCore, &Species, usize) -> Genome + Send + Sync,
+{
+    fn create(&self, rng: &mut dyn RngCore, species: &Species, genome_len: usize) -> Genome {
+        self(rng, species, genome_len)
+    }
+}
+
+impl<F> PlacementHook for F
+where
+    F: Fn(&World, &mut [Individual], &mut dyn RngCore) + Send + Sync,
+{
+    fn place(&self, world: &World, indivs: &mut [Individual], rng: &mut dyn RngCore) {
+        self(world, indivs, rng)
+    }
+}
+
+impl<F> MutationHook for F
+where
+    F: Fn(&mut dyn RngCore, &Species, &mut Genome) + Send + Sync,
+{
+    fn mutate(&self, rng: &mut dyn RngCore, species: &Species, genome: &mut Genome) {
+        self(rng, species, genome)
+    }
+}
+
+impl<F> SelectionHook for F
+where
+    F: Fn(&mut dyn RngCore, &World, &[usize]) -> usize + Send + Sync,
+{
+    fn select(&self, rng: &mut dyn RngCore, world: &World, candidates: &[usize]) -> usize {
+        self(rng, world, candidates)
+    }
+}
+
+/// Random bits in the gene layout of the species
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RandomGenome;
+
+impl GenomeHook for RandomGenome {
+    fn create(&self, rng: &mut dyn RngCore, species: &Species, genome_len: usize) -> Genome {
+        gene_functions::random_layout_genome(rng, genome_len, &species.gene_layout)
+    }
+}
+
+/// Random tiles anywhere on the grid
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RandomPlacement;
+
+impl PlacementHook for RandomPlacement {
+    fn place(&self, world: &World, indivs: &mut [Individual], rng: &mut dyn RngCore) {
+        let mut grid_indicies: Vec<usize> = (0..world.grid.size.x * world.grid.size.y).collect();
+        grid_indicies.shuffle(rng);
+
+        for (indiv, grid_index) in indivs.iter_mut().zip(grid_indicies) {
+            indiv.grid_index = grid_index;
+        }
+    }
+}
+
+/// Bit flips at the mutation rate of the species, see gene_functions::mutate_genome
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BitFlipMutation;
+
+impl MutationHook for BitFlipMutation {
+    fn mutate(&self, rng: &mut dyn RngCore, species: &Species, genome: &mut Genome) {
+        gene_functions::mutate_genome(rng, species.mutation_rate, genome, &species.gene_layout);
+    }
+}
+
+/// Every candidate is equally likely
+#[derive(Debug, Clone, Copy, Default)]
+pub struct UniformSelection;
+
+impl SelectionHook for UniformSelection {
+    fn select(&self, rng: &mut dyn RngCore, _world: &World, candidates: &[usize]) -> usize {
+        candidates[rng.gen_range(0..candidates.len())]
+    }
+}
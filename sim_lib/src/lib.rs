@@ -6,12 +6,22 @@ pub mod basic_types;
 pub mod combined_types;
 
 pub mod network;
+pub mod network_batch;
+pub mod network_view;
 pub mod action_neurons;
 pub mod sensor_neurons;
 pub mod gene_functions;
+pub mod genome_library;
+pub mod hooks;
+pub mod placement;
 pub mod simulation;
 pub mod index_functions;
 pub mod survival_criteria;
+pub mod species;
+pub mod speciation;
+pub mod lineage;
+pub mod diversity;
+pub mod analysis;
 
 pub use combined_types::*;
 pub use basic_types::*;
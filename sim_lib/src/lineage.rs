@@ -0,0 +1,314 @@
+use std::collections::{HashMap, HashSet};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LineageRecord {
+    pub id: usize,
+    pub parent: Option<usize>,
+    pub generation: usize,
+    pub species: usize,
+}
+
+/// Every individual ever born, with its parent. Ids are handed out in order. Without pruning
+/// every record is kept, see prune.
+#[derive(Debug, Clone, Default)]
+pub struct Lineage {
+    records: HashMap<usize, LineageRecord>,
+    next_id: usize,
+    births: Vec<Vec<usize>>, // ids born in each generation
+}
+
+impl Lineage {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Record a birth and return the id of the new individual
+    pub fn register(&mut self, parent: Option<usize>, generation: usize, species: usize) -> usize {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.records.insert(id, LineageRecord { id, parent, generation, species });
+
+        if self.births.len() <= generation {
+            self.births.resize(generation + 1, vec![]);
+        }
+        self.births[generation].push(id);
+
+        id
+    }
+
+    /// Drop the records of everyone without a descendant among the living ids. The living and
+    /// all their ancestors are kept, so ancestors, common_ancestor and to_newick work as before for them.
+    /// Unknown ids are ignored
+    pub fn prune(&mut self, living: &[usize]) {
+        let mut keep = HashSet::new();
+
+        for &id in living {
+            let mut current = Some(id);
+            while let Some(id) = current {
+                if !keep.insert(id) {
+                    break;
+                }
+                current = self.records.get(&id).and_then(|record| record.parent);
+            }
+        }
+
+        self.records.retain(|id, _| keep.contains(id));
+        for births in &mut self.births {
+            births.retain(|id| keep.contains(id));
+        }
+    }
+
+    pub fn get(&self, id: usize) -> Option<&LineageRecord> {
+        self.records.get(&id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.records.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.records.is_empty()
+    }
+
+    pub fn births(&self, generation: usize) -> &[usize] {
+        self.births.get(generation).map(|b| &b[..]).unwrap_or(&[])
+    }
+
+    /// Parent, grand parent and so on, nearest first. None if the id is unknown or pruned
+    pub fn ancestors(&self, id: usize) -> Option<Vec<usize>> {
+        let mut res = vec![];
+        let mut current = self.records.get(&id)?.parent;
+
+        while let Some(parent) = current {
+            res.push(parent);
+            current = self.records.get(&parent).and_then(|record| record.parent);
+        }
+
+        Some(res)
+    }
+
+    /// The most recent individual that all ids descend from. An id counts as its own ancestor here,
+    /// so the common ancestor of a single id is itself. None if any id is unknown or pruned.
+    pub fn common_ancestor(&self, ids: &[usize]) -> Option<usize> {
+        let (first, rest) = ids.split_first()?;
+
+        let mut line = vec![*first];
+        line.extend(self.ancestors(*first)?);
+
+        // Index into line of the oldest ancestor still shared
+        let mut shared = 0;
+        for &id in rest {
+            let mut others: HashSet<usize> = self.ancestors(id)?.into_iter().collect();
+            others.insert(id);
+
+            match line.iter().position(|a| others.contains(a)) {
+                Some(pos) => shared = usize::max(shared, pos),
+                None => return None,
+            }
+        }
+
+        Some(line[shared])
+    }
+
+    /// Phylogeny of the given ids in Newick format. Only their ancestors are included, and
+    /// ancestors with a single child are collapsed into the branch. Branch lengths are generations.
+    /// Unknown or pruned ids are left out.
+    pub fn to_newick(&self, ids: &[usize]) -> String {
+        let ids: Vec<usize> = ids.iter().copied().filter(|id| self.records.contains_key(id)).collect();
+        let leaves: HashSet<usize> = ids.iter().copied().collect();
+        let mut children: HashMap<usize, Vec<usize>> = HashMap::new();
+        let mut roots = vec![];
+        let mut seen = HashSet::new();
+
+        for &id in &ids {
+            let mut current = id;
+            if !seen.insert(current) {
+                continue;
+            }
+
+            loop {
+                // Only known ids get into the tree, so the records indexed below all exist
+                let parent = self.records.get(&current).and_then(|record| record.parent);
+                match parent.filter(|parent| self.records.contains_key(parent)) {
+                    Some(parent) => {
+                        children.entry(parent).or_default().push(current);
+                        if !seen.insert(parent) {
+                            break;
+                        }
+                        current = parent;
+                    }
+                    None => {
+                        roots.push(current);
+                        break;
+                    }
+                }
+            }
+        }
+
+        roots.sort_unstable();
+        for list in children.values_mut() {
+            list.sort_unstable();
+        }
+
+        // Nodes to write, last first. Written without recursion, deep lineages would overflow the stack
+        let mut stack = vec![];
+        if roots.len() > 1 {
+            stack.push(Newick::Close(None, None));
+        }
+        push_children(&mut stack, &roots, None);
+
+        let mut out = String::new();
+        if roots.len() > 1 {
+            out.push('(');
+        }
+
+        while let Some(step) = stack.pop() {
+            match step {
+                Newick::Comma => out.push(','),
+                Newick::Node(mut id, parent_generation) => {
+                    // collapse chains of single children
+                    while !leaves.contains(&id) && children.get(&id).map(|c| c.len()) == Some(1) {
+                        id = children[&id][0];
+                    }
+
+                    match children.get(&id) {
+                        Some(kids) => {
+                            out.push('(');
+                            stack.push(Newick::Close(Some(id), parent_generation));
+                            push_children(&mut stack, kids, Some(self.records[&id].generation));
+                        }
+                        None => self.newick_label(id, parent_generation, &mut out),
+                    }
+                }
+                Newick::Close(id, parent_generation) => {
+                    out.push(')');
+                    if let Some(id) = id {
+                        self.newick_label(id, parent_generation, &mut out);
+                    }
+                }
+            }
+        }
+
+        out.push(';');
+        out
+    }
+
+    fn newick_label(&self, id: usize, parent_generation: Option<usize>, out: &mut String) {
+        out.push_str(&id.to_string());
+
+        if let Some(parent_generation) = parent_generation {
+            out.push_str(&format!(":{}", self.records[&id].generation - parent_generation));
+        }
+    }
+}
+
+/// A step of writing to_newick. Close of None is the unnamed root joining several roots
+enum Newick {
+    Node(usize, Option<usize>), // id and generation of the parent
+    Close(Option<usize>, Option<usize>),
+    Comma,
+}
+
+/// Push the nodes so they pop in order, separated by commas
+fn push_children(stack: &mut Vec<Newick>, ids: &[usize], parent_generation: Option<usize>) {
+    for (i, &id) in ids.iter().enumerate().rev() {
+        stack.push(Newick::Node(id, parent_generation));
+        if i > 0 {
+            stack.push(Newick::Comma);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    /// 0 and 1 are roots. 0 -> 2 -> (3, 4), 1 -> 5
+    fn test_lineage() -> Lineage {
+        let mut lineage = Lineage::new();
+        lineage.register(None, 0, 0);
+        lineage.register(None, 0, 0);
+        lineage.register(Some(0), 1, 0);
+        lineage.register(Some(2), 2, 0);
+        lineage.register(Some(2), 2, 0);
+        lineage.register(Some(1), 2, 0);
+        lineage
+    }
+
+    #[test]
+    fn ancestors() {
+        let lineage = test_lineage();
+
+        assert_eq!(Some(vec![2, 0]), lineage.ancestors(3));
+        assert_eq!(Some(vec![]), lineage.ancestors(0));
+        assert_eq!(None, lineage.ancestors(6));
+        assert_eq!(&[3, 4, 5], lineage.births(2));
+    }
+
+    #[test]
+    fn common_ancestor() {
+        let lineage = test_lineage();
+
+        assert_eq!(Some(2), lineage.common_ancestor(&[3, 4]));
+        assert_eq!(Some(2), lineage.common_ancestor(&[3, 2]));
+        assert_eq!(Some(3), lineage.common_ancestor(&[3]));
+        assert_eq!(None, lineage.common_ancestor(&[3, 5]));
+        assert_eq!(None, lineage.common_ancestor(&[]));
+        assert_eq!(None, lineage.common_ancestor(&[3, 6]));
+    }
+
+    #[test]
+    fn newick() {
+        let lineage = test_lineage();
+
+        // 0 has a single child, so it is collapsed into the branch to 2
+        assert_eq!("(3:1,4:1)2;", lineage.to_newick(&[3, 4]));
+
+        // Several roots are joined under an unnamed root
+        assert_eq!("((3:1,4:1)2,5);", lineage.to_newick(&[3, 4, 5]));
+        assert_eq!("5;", lineage.to_newick(&[5]));
+
+        // Unknown ids are left out
+        assert_eq!("(3:1,4:1)2;", lineage.to_newick(&[3, 6, 4]));
+    }
+
+    #[test]
+    fn deep_newick() {
+        // Each generation one child ends the line and the other goes on
+        let mut lineage = Lineage::new();
+        let mut line = lineage.register(None, 0, 0);
+        let mut leaves = vec![];
+        for generation in 1..50_000 {
+            leaves.push(lineage.register(Some(line), generation, 0));
+            line = lineage.register(Some(line), generation, 0);
+        }
+        leaves.push(line);
+
+        let newick = lineage.to_newick(&leaves);
+        assert!(newick.starts_with("(1:1,(3:1,(5:1,"));
+        assert!(newick.ends_with(")0;"));
+    }
+
+    #[test]
+    fn prune() {
+        let mut lineage = test_lineage();
+        lineage.prune(&[3]);
+
+        assert_eq!(3, lineage.len());
+        assert_eq!(None, lineage.get(1));
+        assert_eq!(None, lineage.get(4));
+        assert_eq!(Some(vec![2, 0]), lineage.ancestors(3));
+        assert_eq!(&[3], lineage.births(2));
+
+        // Pruned and unknown ids don't panic
+        assert_eq!(None, lineage.ancestors(4));
+        assert_eq!(None, lineage.common_ancestor(&[3, 4]));
+        assert_eq!("3;", lineage.to_newick(&[3, 4]));
+        lineage.prune(&[3, 4, 10]);
+        assert_eq!(3, lineage.len());
+
+        // Ids are not reused
+        assert_eq!(6, lineage.register(Some(3), 3, 0));
+    }
+}
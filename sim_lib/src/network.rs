@@ -1,6 +1,9 @@
 use crate::basic_types::*;
 use crate::combined_types::*;
+use crate::network_view::*;
 use crate::sensor_neurons;
+use crate::gene_functions;
+use crate::species::Species;
 
 
 const WEIGHT_SCALE: f64 = 10_000.0;
@@ -24,31 +27,43 @@ impl Connection {
 struct Neuron {
     value: f64,
     action : Option::<Action>, // if None it is hidden, if not none it is index into config output Index
+    activation: ActivationFunction,
 }
 
 
 impl Neuron {
 
-    fn hidden() -> Self {
+    fn hidden(activation: ActivationFunction) -> Self {
         Self {
             value : 0.0,
-            action: None
+            action: None,
+            activation,
         }
     }
 
     fn action(action: Action) -> Self {
         Self {
             value : 0.0,
-            action: Some(action)
+            action: Some(action),
+            activation: ActivationFunction::Tanh,
         }
     }
+
+    fn output(&self) -> f64 {
+        self.activation.apply(self.value)
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct Network {
     sensor_inputs: Vec::<Connection>, // sensor index index to neuron index
     hidden_connections: Vec::<Connection>,
+    recurrent_connections: Vec::<Connection>, // read previous, see HiddenEvaluation::Recurrent
     neurons: Vec::<Neuron>, // valu1es of hidden and action neurons
+    previous: Vec::<f64>, // neuron values at the end of the last step
+    used_sensors: Vec::<usize>, // distinct sensor indices with a connection
+    sensor_values: Vec::<f64>, // reading of each sensor index this step, only used sensors are set
+    evaluation: HiddenEvaluation,
     action_neuron_map: std::collections::HashMap::<usize,  usize> // maps action_neuron index in config to network specific neuron. Only used on create from genome
 }
 
@@ -58,34 +73,59 @@ impl Network {
         Network {
             sensor_inputs: Vec::<Connection>::new(),
             hidden_connections: Vec::<Connection>::new(),
+            recurrent_connections: Vec::<Connection>::new(),
             neurons: Vec::<Neuron>::new(),
+            previous: Vec::<f64>::new(),
+            used_sensors: Vec::<usize>::new(),
+            sensor_values: Vec::<f64>::new(),
+            evaluation: HiddenEvaluation::GenomeOrder,
             action_neuron_map: std::collections::HashMap::new(),
         }
     }
 
 
-    pub fn initialize_from_genome(&mut self, genome: &Genome, config: &Configuration, sensor_neurons: &Vec::<Sensor>, action_neurons: &Vec::<Action>) {
+    /// Decode the genome with the brain layout of the species
+    pub fn initialize_from_genome(&mut self, genome: &Genome, species: &Species) {
+        let hidden_neurons = species.hidden_neurons;
+        let evaluation = species.hidden_evaluation;
+        let sensor_neurons = &species.sensor_neurons;
+        let action_neurons = &species.action_neurons;
+        let layout = &species.gene_layout;
 
         self.sensor_inputs.clear();
         self.neurons.clear();
         self.hidden_connections.clear();
+        self.recurrent_connections.clear();
         self.action_neuron_map.clear();
+        self.evaluation = evaluation;
 
         let inputs_count = sensor_neurons.len();
-        let input_and_hidden_count = inputs_count + config.hidden_neurons;
 
 
         // setup hidden neurons
-        for _ in 0..config.hidden_neurons {
-            self.neurons.push(Neuron::hidden())
-        }
+        let connection_genes = match species.activation {
+            NeuronActivation::Network(function) => {
+                for _ in 0..hidden_neurons {
+                    self.neurons.push(Neuron::hidden(function))
+                }
+                &genome[..]
+            },
+            NeuronActivation::Genome => {
+                // neurons without a gene, in a genome shorter than hidden_neurons, use tanh
+                for i in 0..hidden_neurons {
+                    let function = genome.get(i).map_or(ActivationFunction::Tanh, gene_functions::neuron_gene_activation);
+                    self.neurons.push(Neuron::hidden(function))
+                }
+                species.connection_genes(genome)
+            },
+        };
 
-        for gene in genome {
-            let input_index = (gene.from_neuron as usize % input_and_hidden_count) as usize;
-            let output_index = self.get_output_index(config.hidden_neurons, gene.to_neuron as usize, &action_neurons);
+        for gene in connection_genes {
+            let input_index = layout.source_index(gene, inputs_count, hidden_neurons);
+            let output_index = self.get_output_index(hidden_neurons, layout.sink_index(gene, hidden_neurons, action_neurons.len()), action_neurons);
 
             // scale weight from i16 range to a smaller f64 range. Along -4..4
-            let weight = (gene.weight as f64) / WEIGHT_SCALE;
+            let weight = (gene.weight as f64) * layout.weight_factor() / WEIGHT_SCALE;
 
             if input_index < inputs_count {
                 // This gene starts from a sensor input
@@ -96,12 +136,39 @@ impl Network {
                 self.hidden_connections.push(Connection::new(input_index - inputs_count, output_index, weight));
             }
         }
+
+        if evaluation != HiddenEvaluation::GenomeOrder {
+            // Hidden neurons come before action neurons, so a connection to a higher index is forward
+            let (mut forward, backward): (Vec<_>, Vec<_>) = std::mem::take(&mut self.hidden_connections)
+                .into_iter()
+                .partition(|c| c.output_index > c.input_index);
+
+            // All inputs of a neuron come from lower indices, so they are in before it is read
+            forward.sort_by_key(|c| c.input_index);
+            self.hidden_connections = forward;
+
+            if evaluation == HiddenEvaluation::Recurrent {
+                self.recurrent_connections = backward;
+            }
+        }
+
+        self.previous = vec![0.0; self.neurons.len()];
+        self.sensor_values = vec![0.0; inputs_count];
+        self.update_used_sensors();
+    }
+
+    fn update_used_sensors(&mut self) {
+        self.used_sensors.clear();
+        for con in &self.sensor_inputs {
+            if !self.used_sensors.contains(&con.input_index) {
+                self.used_sensors.push(con.input_index);
+            }
+        }
     }
 
 
-    fn get_output_index(&mut self, hidden_neurons: usize, mut to_neuron: usize, action_neurons: &Vec::<Action>) -> usize {
+    fn get_output_index(&mut self, hidden_neurons: usize, to_neuron: usize, action_neurons: &[Action]) -> usize {
 
-        to_neuron = to_neuron % (hidden_neurons + action_neurons.len());
         if to_neuron < hidden_neurons {
             to_neuron
         }
@@ -124,6 +191,155 @@ impl Network {
     }
 
 
+    /// Remove connections and hidden neurons that cannot change any action, and sum duplicate
+    /// connections between the same neurons. All action neurons are kept, so run returns the
+    /// same activations as before, up to float rounding of the summed weights.
+    pub fn prune(&mut self) {
+        let count = self.neurons.len();
+
+        // neurons with a path to an action neuron
+        let mut useful: Vec<bool> = self.neurons.iter().map(|n| n.action.is_some()).collect();
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for con in self.hidden_connections.iter().chain(&self.recurrent_connections) {
+                if useful[con.output_index] && !useful[con.input_index] {
+                    useful[con.input_index] = true;
+                    changed = true;
+                }
+            }
+        }
+
+        // neurons with a path from a sensor, or that give output without input like sigmoid.
+        // All others always output 0
+        let mut fed: Vec<bool> = self.neurons.iter().map(|n| n.action.is_none() && n.activation.apply(0.0) != 0.0).collect();
+        for con in &self.sensor_inputs {
+            fed[con.output_index] = true;
+        }
+        changed = true;
+        while changed {
+            changed = false;
+            for con in self.hidden_connections.iter().chain(&self.recurrent_connections) {
+                if fed[con.input_index] && !fed[con.output_index] {
+                    fed[con.output_index] = true;
+                    changed = true;
+                }
+            }
+        }
+
+        let mut new_index = vec![None; count];
+        let mut neurons = vec![];
+        let mut previous = vec![];
+        for i in 0..count {
+            if self.neurons[i].action.is_some() || (useful[i] && fed[i]) {
+                new_index[i] = Some(neurons.len());
+                neurons.push(self.neurons[i]);
+                previous.push(self.previous[i]);
+            }
+        }
+
+        let sensor_inputs: Vec<Connection> = self.sensor_inputs.iter()
+            .filter_map(|c| new_index[c.output_index].map(|output_index| Connection::new(c.input_index, output_index, c.weight)))
+            .collect();
+
+        let remap_hidden = |connections: &[Connection]| -> Vec<Connection> {
+            connections.iter()
+                .filter_map(|c| match (new_index[c.input_index], new_index[c.output_index]) {
+                    (Some(input_index), Some(output_index)) => Some(Connection::new(input_index, output_index, c.weight)),
+                    _ => None,
+                })
+                .collect()
+        };
+
+        // sensor and recurrent connections only read values that do not change during the step,
+        // so the order between them does not matter
+        self.sensor_inputs = merge_duplicates(sensor_inputs, false);
+        self.hidden_connections = merge_duplicates(remap_hidden(&self.hidden_connections), self.evaluation == HiddenEvaluation::GenomeOrder);
+        self.recurrent_connections = merge_duplicates(remap_hidden(&self.recurrent_connections), false);
+
+        for idx in self.action_neuron_map.values_mut() {
+            *idx = new_index[*idx].unwrap();
+        }
+
+        self.neurons = neurons;
+        self.previous = previous;
+        self.update_used_sensors();
+    }
+
+
+    /// Read only view of the network, with names from the sensor list it was created with
+    pub fn view(&self, sensor_neurons: &[Sensor]) -> NetworkView {
+        let node = |neuron_index: usize| match self.neurons[neuron_index].action {
+            Some(action) => Node::Action(action),
+            None => Node::Hidden(neuron_index),
+        };
+
+        let mut connections = vec![];
+        let mut sensors = vec![];
+
+        for con in &self.sensor_inputs {
+            let sensor = sensor_neurons[con.input_index];
+            if !sensors.contains(&sensor) {
+                sensors.push(sensor);
+            }
+            connections.push(ConnectionView { from: Node::Sensor(sensor), to: node(con.output_index), weight: con.weight, recurrent: false });
+        }
+
+        for con in &self.recurrent_connections {
+            connections.push(ConnectionView { from: node(con.input_index), to: node(con.output_index), weight: con.weight, recurrent: true });
+        }
+
+        for con in &self.hidden_connections {
+            connections.push(ConnectionView { from: node(con.input_index), to: node(con.output_index), weight: con.weight, recurrent: false });
+        }
+
+        NetworkView {
+            sensors,
+            hidden: (0..self.neurons.len()).filter(|&i| self.neurons[i].action.is_none()).collect(),
+            activations: self.neurons.iter().filter(|n| n.action.is_none()).map(|n| n.activation).collect(),
+            actions: self.neurons.iter().filter_map(|n| n.action).collect(),
+            connections,
+        }
+    }
+
+    /// (sensor index, neuron index, weight) for every connection from a sensor
+    pub(crate) fn sensor_edges(&self) -> impl Iterator<Item = (usize, usize, f64)> + '_ {
+        self.sensor_inputs.iter().map(|c| (c.input_index, c.output_index, c.weight))
+    }
+
+    /// (neuron index, neuron index, weight) for every connection from a hidden neuron
+    pub(crate) fn hidden_edges(&self) -> impl Iterator<Item = (usize, usize, f64)> + '_ {
+        self.recurrent_connections.iter().chain(&self.hidden_connections).map(|c| (c.input_index, c.output_index, c.weight))
+    }
+
+    /// Like hidden_edges, but only the connections that read the values of this step, in evaluation order
+    pub(crate) fn current_edges(&self) -> impl Iterator<Item = (usize, usize, f64)> + '_ {
+        self.hidden_connections.iter().map(|c| (c.input_index, c.output_index, c.weight))
+    }
+
+    /// Connections that read the values of the previous step, see HiddenEvaluation::Recurrent
+    pub(crate) fn recurrent_edges(&self) -> impl Iterator<Item = (usize, usize, f64)> + '_ {
+        self.recurrent_connections.iter().map(|c| (c.input_index, c.output_index, c.weight))
+    }
+
+    pub(crate) fn neuron_activation(&self, neuron_index: usize) -> ActivationFunction {
+        self.neurons[neuron_index].activation
+    }
+
+    /// Neuron values at the end of the last step, read by recurrent connections
+    pub(crate) fn previous_values(&self) -> &[f64] {
+        &self.previous
+    }
+
+    pub(crate) fn neuron_count(&self) -> usize {
+        self.neurons.len()
+    }
+
+    /// The action of the neuron, None for hidden neurons
+    pub(crate) fn neuron_action(&self, neuron_index: usize) -> Option<Action> {
+        self.neurons[neuron_index].action
+    }
+
     pub fn run(&mut self, sensor_neurons: &Vec::<Sensor>, world: &World, individual: &Individual) -> Vec<Activation> {
 
         // reset old values
@@ -131,35 +347,86 @@ impl Network {
             self.neurons[i].value = 0.0;
         }
 
+        // read each sensor once, so all its connections see the same value
+        for &sensor_index in &self.used_sensors {
+            self.sensor_values[sensor_index] = sensor_neurons::get_sensor_input(sensor_neurons[sensor_index], world, individual);
+        }
+
         // go over all over all sensor input
         for sensor_con in &self.sensor_inputs {
-            let sensor = sensor_neurons[sensor_con.input_index];
-            let reading = sensor_neurons::get_sensor_input(sensor, world, individual);
+            let reading = self.sensor_values[sensor_con.input_index];
             self.neurons[sensor_con.output_index].value += reading * sensor_con.weight;
         }
 
+        // recurrent connections read the previous step, so they can go before all other hidden
+        for con in &self.recurrent_connections {
+            let reading = self.neurons[con.input_index].activation.apply(self.previous[con.input_index]);
+            self.neurons[con.output_index].value += reading * con.weight;
+        }
+
         // go over all hidden neuron
         for hidden_con in &self.hidden_connections {
 
-            let reading = f64::tanh(self.neurons[hidden_con.input_index].value);
+            let reading = self.neurons[hidden_con.input_index].output();
 
             self.neurons[hidden_con.output_index].value += reading * hidden_con.weight;
         }
 
+        if !self.recurrent_connections.is_empty() {
+            for (previous, neuron) in self.previous.iter_mut().zip(&self.neurons) {
+                *previous = neuron.value;
+            }
+        }
+
         self.neurons.iter().filter(|n| n.action.is_some()).map(|n|
                                                                Activation {
                                                                    action: n.action.unwrap(),
-                                                                   weight: f64::tanh(n.value),
+                                                                   weight: n.output(),
                                                                    indiv_index: individual.index
                                                                }).collect()
     }
 }
 
+/// Sum connections between the same neurons into the first of them. With in_order the
+/// connections are run one after the other, and a connection is only merged into an earlier
+/// one when nothing in between changes its input or reads its output.
+fn merge_duplicates(connections: Vec<Connection>, in_order: bool) -> Vec<Connection> {
+    let mut merged: Vec<Connection> = vec![];
+
+    'connections: for con in connections {
+        if !in_order || con.input_index != con.output_index {
+            for existing in merged.iter_mut().rev() {
+                if existing.input_index == con.input_index && existing.output_index == con.output_index {
+                    existing.weight += con.weight;
+                    continue 'connections;
+                }
+                if in_order && (existing.output_index == con.input_index || existing.input_index == con.output_index) {
+                    break;
+                }
+            }
+        }
+        merged.push(con);
+    }
+
+    merged
+}
+
 #[cfg(test)]
 mod tests {
 
     use super::*;
 
+    fn brain(sensor_neurons: &[Sensor], action_neurons: &[Action], hidden_neurons: usize, hidden_evaluation: HiddenEvaluation, activation: NeuronActivation) -> Species {
+        Species {
+            sensor_neurons: sensor_neurons.to_vec(),
+            action_neurons: action_neurons.to_vec(),
+            hidden_neurons,
+            hidden_evaluation,
+            activation,
+            ..Species::new("test", 0)
+        }
+    }
+
     #[test]
     fn zero_weight() {
         let gene = Gene { from_neuron: 0, to_neuron: 0, weight: 0 };
@@ -173,7 +440,7 @@ mod tests {
 
         let mut network = Network::empty();
 
-        network.initialize_from_genome(&vec![ gene ], &config, &sensor_neurons, &action_neurons);
+        network.initialize_from_genome(&vec![ gene ], &brain(&sensor_neurons, &action_neurons, config.hidden_neurons, HiddenEvaluation::GenomeOrder, NeuronActivation::default()));
 
         let world = World::new(Coord {x: 128, y: 128});
         let indiv = Individual::new();
@@ -197,7 +464,7 @@ mod tests {
     #[test]
     fn weight_1() {
 
-        let gene = Gene { from_neuron: 0, to_neuron: 1, weight: WEIGHT_SCALE as i16 };
+        let gene = Gene { from_neuron: 0, to_neuron: 1, weight: WEIGHT_SCALE as i32 };
 
         let mut config = Configuration::default();
         let sensor_neurons = vec![Sensor::Constant, Sensor::Constant, Sensor::Constant];
@@ -206,7 +473,7 @@ mod tests {
 
         let mut network = Network::empty();
 
-        network.initialize_from_genome(&vec![ gene ], &config, &sensor_neurons, &action_neurons);
+        network.initialize_from_genome(&vec![ gene ], &brain(&sensor_neurons, &action_neurons, config.hidden_neurons, HiddenEvaluation::GenomeOrder, NeuronActivation::default()));
 
 
         let world = World::new(Coord {x: 128, y: 128});
@@ -231,9 +498,9 @@ mod tests {
     fn weight_negative_hidden() {
 
 
-        let gene = Gene { from_neuron: 0, to_neuron: 0, weight: WEIGHT_SCALE as i16};
+        let gene = Gene { from_neuron: 0, to_neuron: 0, weight: WEIGHT_SCALE as i32};
 
-        let gene1 = Gene { from_neuron: 1, to_neuron: 1, weight: - WEIGHT_SCALE as i16 };
+        let gene1 = Gene { from_neuron: 1, to_neuron: 1, weight: - WEIGHT_SCALE as i32 };
 
         let mut config = Configuration::default();
         let sensor_neurons = vec![Sensor::Constant, Sensor::Constant, Sensor::Constant];
@@ -243,7 +510,7 @@ mod tests {
 
         let mut network = Network::empty();
 
-        network.initialize_from_genome(&vec![ gene, gene1 ], &config, &sensor_neurons, &action_neurons);
+        network.initialize_from_genome(&vec![ gene, gene1 ], &brain(&sensor_neurons, &action_neurons, config.hidden_neurons, HiddenEvaluation::GenomeOrder, NeuronActivation::default()));
 
 
         let world = World::new(Coord {x: 128, y: 128});
@@ -263,4 +530,223 @@ mod tests {
         assert_eq!(f64::tanh(-1.0), actions[0].weight);
 
     }
+
+    #[test]
+    fn prune_removes_dead_and_duplicates() {
+        let sensor_neurons = vec![Sensor::Constant];
+        let action_neurons = vec![Action::MoveForward, Action::MoveX];
+
+        // from 0 is the sensor, from 1..=2 hidden. to 0..=1 hidden, to 2.. actions
+        let genome = vec![
+            Gene { from_neuron: 0, to_neuron: 2, weight: 1000 }, // sensor -> MoveForward
+            Gene { from_neuron: 0, to_neuron: 2, weight: 2000 }, // duplicate
+            Gene { from_neuron: 0, to_neuron: 0, weight: 1000 }, // sensor -> hidden 0, which goes nowhere
+            Gene { from_neuron: 2, to_neuron: 3, weight: 1000 }, // hidden 1, never fed -> MoveX
+        ];
+
+        let mut network = Network::empty();
+        network.initialize_from_genome(&genome, &brain(&sensor_neurons, &action_neurons, 2, HiddenEvaluation::GenomeOrder, NeuronActivation::default()));
+        network.prune();
+
+        assert_eq!(2, network.neuron_count());
+        assert_eq!(0, network.hidden_edges().count());
+
+        let edges: Vec<_> = network.sensor_edges().collect();
+        assert_eq!(1, edges.len());
+        assert_relative_eq!(0.3, edges[0].2);
+
+        let world = World::new(Coord {x: 128, y: 128});
+        let actions = network.run(&sensor_neurons, &world, &Individual::new());
+        assert_eq!(2, actions.len());
+        assert_relative_eq!(f64::tanh(0.3), actions[0].weight);
+        assert_eq!(0.0, actions[1].weight);
+    }
+
+    #[test]
+    fn prune_same_output() {
+        let mut rng = rand::thread_rng();
+        let sensor_neurons: Vec<Sensor> = all_sensors().into_iter().filter(|&s| s != Sensor::Random).collect();
+        let action_neurons = all_actions();
+
+        let mut world = World::new(Coord {x: 64, y: 64});
+        let mut indiv = Individual::new();
+        indiv.grid_index = 64 * 20 + 13;
+        world.add_individual(indiv);
+
+        let evaluations = [HiddenEvaluation::GenomeOrder, HiddenEvaluation::FeedForward, HiddenEvaluation::Recurrent];
+
+        // short genomes with few neurons give many duplicates and hidden chains
+        let activations = [
+            NeuronActivation::default(),
+            NeuronActivation::Network(ActivationFunction::Sigmoid),
+            NeuronActivation::Genome,
+        ];
+
+        for (evaluation, activation) in evaluations.iter().flat_map(|e| activations.iter().map(move |a| (e, a))) {
+            for genome_length in &[4, 16, 64] {
+                for _ in 0..100 {
+                    let genome = crate::gene_functions::random_genome(&mut rng, *genome_length);
+
+                    let mut full = Network::empty();
+                    full.initialize_from_genome(&genome, &brain(&sensor_neurons, &action_neurons, 3, *evaluation, *activation));
+                    let mut pruned = full.clone();
+                    pruned.prune();
+
+                    assert!(pruned.sensor_inputs.len() <= full.sensor_inputs.len());
+                    assert!(pruned.hidden_connections.len() <= full.hidden_connections.len());
+
+                    // a few steps, so recurrent state is compared too
+                    for _ in 0..3 {
+                        let expected = full.run(&sensor_neurons, &world, &world.individuals[0]);
+                        let actual = pruned.run(&sensor_neurons, &world, &world.individuals[0]);
+
+                        assert_eq!(expected.len(), actual.len());
+                        for (e, a) in expected.iter().zip(actual.iter()) {
+                            assert_eq!(e.action, a.action);
+                            assert_relative_eq!(e.weight, a.weight, epsilon = 1e-9);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn self_loop_holds_state() {
+        let sensor_neurons = vec![Sensor::Energy];
+        let action_neurons = vec![Action::MoveX];
+
+        // from 0 is the sensor, from 1 hidden. to 0 hidden, to 1 MoveX
+        let genome = vec![
+            Gene { from_neuron: 0, to_neuron: 0, weight: 10_000 }, // energy -> H0
+            Gene { from_neuron: 1, to_neuron: 0, weight: 30_000 }, // H0 -> H0
+            Gene { from_neuron: 1, to_neuron: 1, weight: 10_000 }, // H0 -> MoveX
+        ];
+
+        let world = World::new(Coord {x: 16, y: 16});
+        let mut indiv = Individual::new();
+
+        let mut recurrent = Network::empty();
+        recurrent.initialize_from_genome(&genome, &brain(&sensor_neurons, &action_neurons, 1, HiddenEvaluation::Recurrent, NeuronActivation::default()));
+        recurrent.prune();
+
+        let mut feed_forward = Network::empty();
+        feed_forward.initialize_from_genome(&genome, &brain(&sensor_neurons, &action_neurons, 1, HiddenEvaluation::FeedForward, NeuronActivation::default()));
+
+        // only the first step sees any input
+        let first = recurrent.run(&sensor_neurons, &world, &indiv);
+        assert_relative_eq!(f64::tanh(f64::tanh(1.0)), first[0].weight);
+        assert_relative_eq!(first[0].weight, feed_forward.run(&sensor_neurons, &world, &indiv)[0].weight);
+
+        indiv.energy = 0.0;
+        for _ in 0..10 {
+            assert!(recurrent.run(&sensor_neurons, &world, &indiv)[0].weight > 0.7);
+            assert_eq!(0.0, feed_forward.run(&sensor_neurons, &world, &indiv)[0].weight);
+        }
+
+        // decoding again clears the state
+        recurrent.initialize_from_genome(&genome, &brain(&sensor_neurons, &action_neurons, 1, HiddenEvaluation::Recurrent, NeuronActivation::default()));
+        assert_eq!(0.0, recurrent.run(&sensor_neurons, &world, &indiv)[0].weight);
+    }
+
+    #[test]
+    fn ordered_evaluation_ignores_gene_order() {
+        use rand::seq::SliceRandom;
+
+        let mut rng = rand::thread_rng();
+        let sensor_neurons: Vec<Sensor> = all_sensors().into_iter().filter(|&s| s != Sensor::Random).collect();
+        let action_neurons = all_actions();
+
+        let mut world = World::new(Coord {x: 64, y: 64});
+        let mut indiv = Individual::new();
+        indiv.grid_index = 64 * 40 + 7;
+        world.add_individual(indiv);
+
+        for evaluation in &[HiddenEvaluation::FeedForward, HiddenEvaluation::Recurrent] {
+            for _ in 0..200 {
+                let mut genome = crate::gene_functions::random_genome(&mut rng, 32);
+
+                let mut network = Network::empty();
+                network.initialize_from_genome(&genome, &brain(&sensor_neurons, &action_neurons, 4, *evaluation, NeuronActivation::default()));
+
+                genome.shuffle(&mut rng);
+                let mut shuffled = Network::empty();
+                shuffled.initialize_from_genome(&genome, &brain(&sensor_neurons, &action_neurons, 4, *evaluation, NeuronActivation::default()));
+
+                for _ in 0..3 {
+                    let mut expected = network.run(&sensor_neurons, &world, &world.individuals[0]);
+                    let mut actual = shuffled.run(&sensor_neurons, &world, &world.individuals[0]);
+
+                    // action neurons are created in gene order
+                    expected.sort_by_key(|a| a.action as usize);
+                    actual.sort_by_key(|a| a.action as usize);
+
+                    assert_eq!(expected.len(), actual.len());
+                    for (e, a) in expected.iter().zip(actual.iter()) {
+                        assert_eq!(e.action, a.action);
+                        assert_relative_eq!(e.weight, a.weight, epsilon = 1e-9);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn hidden_activation() {
+        let sensor_neurons = vec![Sensor::Constant];
+        let action_neurons = vec![Action::MoveX];
+        let world = World::new(Coord {x: 16, y: 16});
+        let indiv = Individual::new();
+
+        // from 0 is the sensor, from 1 hidden. to 0 hidden, to 1 MoveX
+        let connections = vec![
+            Gene { from_neuron: 0, to_neuron: 0, weight: 20_000 }, // constant -> H0
+            Gene { from_neuron: 1, to_neuron: 1, weight: 10_000 }, // H0 -> MoveX
+        ];
+
+        let mut network = Network::empty();
+        network.initialize_from_genome(&connections, &brain(&sensor_neurons, &action_neurons, 1, HiddenEvaluation::GenomeOrder, NeuronActivation::Network(ActivationFunction::Sigmoid)));
+        let sigmoid = 1.0 / (1.0 + f64::exp(-2.0));
+        assert_relative_eq!(f64::tanh(sigmoid), network.run(&sensor_neurons, &world, &indiv)[0].weight);
+
+        // weight 4 is identity
+        let mut genome = vec![Gene { from_neuron: 0, to_neuron: 0, weight: 4 }];
+        genome.extend(connections);
+        assert_eq!(ActivationFunction::Identity, gene_functions::neuron_gene_activation(&genome[0]));
+
+        network.initialize_from_genome(&genome, &brain(&sensor_neurons, &action_neurons, 1, HiddenEvaluation::GenomeOrder, NeuronActivation::Genome));
+        assert_relative_eq!(f64::tanh(2.0), network.run(&sensor_neurons, &world, &indiv)[0].weight);
+
+        // sigmoid outputs 0.5 without any input, so pruning keeps it
+        let bias = vec![Gene { from_neuron: 1, to_neuron: 1, weight: 10_000 }];
+        network.initialize_from_genome(&bias, &brain(&sensor_neurons, &action_neurons, 1, HiddenEvaluation::GenomeOrder, NeuronActivation::Network(ActivationFunction::Sigmoid)));
+        network.prune();
+        assert_relative_eq!(f64::tanh(0.5), network.run(&sensor_neurons, &world, &indiv)[0].weight);
+    }
+
+    #[test]
+    fn sensor_read_once() {
+        // LocX is not connected and must not be read
+        let sensor_neurons = vec![Sensor::LocX, Sensor::Random];
+        let action_neurons = vec![Action::MoveX, Action::MoveY];
+
+        // random -> MoveX and random -> MoveY
+        let genome = vec![
+            Gene { from_neuron: 1, to_neuron: 0, weight: 10_000 },
+            Gene { from_neuron: 1, to_neuron: 1, weight: 10_000 },
+        ];
+
+        let world = World::new(Coord {x: 16, y: 16});
+        let indiv = Individual::new();
+
+        let mut network = Network::empty();
+        network.initialize_from_genome(&genome, &brain(&sensor_neurons, &action_neurons, 0, HiddenEvaluation::GenomeOrder, NeuronActivation::default()));
+
+        sensor_neurons::READS.with(|reads| reads.borrow_mut().clear());
+        network.run(&sensor_neurons, &world, &indiv);
+        network.run(&sensor_neurons, &world, &indiv);
+
+        let reads = sensor_neurons::READS.with(|reads| reads.borrow().clone());
+        assert_eq!(vec![Sensor::Random, Sensor::Random], reads);
+    }
 }
@@ -0,0 +1,522 @@
+use std::ops::Range;
+
+use crate::basic_types::*;
+use crate::combined_types::*;
+use crate::network::Network;
+use crate::sensor_neurons;
+
+// Networks evaluated on one thread before splitting the work further
+const MIN_SPLIT: usize = 256;
+
+/// Connections as parallel arrays. Indices are local to the network
+#[derive(Debug, Clone, Default)]
+struct Connections {
+    input: Vec<u32>,
+    output: Vec<u32>,
+    weight: Vec<f64>,
+}
+
+impl Connections {
+    fn clear(&mut self) {
+        self.input.clear();
+        self.output.clear();
+        self.weight.clear();
+    }
+
+    fn push(&mut self, input: usize, output: usize, weight: f64) {
+        self.input.push(input as u32);
+        self.output.push(output as u32);
+        self.weight.push(weight);
+    }
+
+    fn len(&self) -> usize {
+        self.weight.len()
+    }
+
+    fn splice(&mut self, range: Range<usize>, other: &Connections) {
+        self.input.splice(range.clone(), other.input.iter().copied());
+        self.output.splice(range.clone(), other.output.iter().copied());
+        self.weight.splice(range, other.weight.iter().copied());
+    }
+}
+
+/// Range of network n in arrays indexed by starts
+fn slot(starts: &[usize], n: usize) -> Range<usize> {
+    starts[n]..starts[n + 1]
+}
+
+/// Move the starts after network n, now that its range is len long
+fn shift(starts: &mut [usize], n: usize, len: usize) {
+    let old_len = starts[n + 1] - starts[n];
+    for start in &mut starts[n + 1..] {
+        *start = *start + len - old_len;
+    }
+}
+
+/// Everything that is fixed between compiles. Ranges of network n are start[n]..start[n + 1]
+#[derive(Debug, Clone, Default)]
+struct Layout {
+    neuron_start: Vec<usize>,
+    sensor_start: Vec<usize>,
+    sensor_con_start: Vec<usize>,
+    recurrent_start: Vec<usize>,
+    hidden_start: Vec<usize>,
+    action_start: Vec<usize>,
+
+    indiv_index: Vec<usize>,
+    activation: Vec<ActivationFunction>, // per neuron
+    sensors: Vec<Sensor>, // distinct sensors each network reads
+    action_neurons: Vec<u32>, // local neuron index of each action neuron
+
+    sensor_cons: Connections, // input is an index into the sensors of the network
+    recurrent_cons: Connections,
+    hidden_cons: Connections,
+}
+
+/// Buffers written every step, in the same order as the layout
+#[derive(Debug, Clone, Default)]
+struct State {
+    values: Vec<f64>,
+    previous: Vec<f64>,
+    sensor_values: Vec<f64>,
+    activations: Vec<Activation>,
+}
+
+struct Buffers<'a> {
+    values: &'a mut [f64],
+    previous: &'a mut [f64],
+    sensor_values: &'a mut [f64],
+    activations: &'a mut [Activation],
+}
+
+impl<'a> Buffers<'a> {
+    /// Split before network mid, where the buffers start at network start
+    fn split(self, layout: &Layout, start: usize, mid: usize) -> (Buffers<'a>, Buffers<'a>) {
+        let (values, values_rest) = self.values.split_at_mut(layout.neuron_start[mid] - layout.neuron_start[start]);
+        let (previous, previous_rest) = self.previous.split_at_mut(layout.neuron_start[mid] - layout.neuron_start[start]);
+        let (sensor_values, sensor_values_rest) = self.sensor_values.split_at_mut(layout.sensor_start[mid] - layout.sensor_start[start]);
+        let (activations, activations_rest) = self.activations.split_at_mut(layout.action_start[mid] - layout.action_start[start]);
+
+        (
+            Buffers { values, previous, sensor_values, activations },
+            Buffers { values: values_rest, previous: previous_rest, sensor_values: sensor_values_rest, activations: activations_rest },
+        )
+    }
+}
+
+/// All networks of a population compiled into flat arrays, CSR style. Evaluates every network
+/// in parallel without allocating, and gives the same activations as Network::run. Each sensor
+/// is read once per network and step.
+#[derive(Debug, Clone, Default)]
+pub struct NetworkBatch {
+    layout: Layout,
+    state: State,
+}
+
+impl NetworkBatch {
+
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Replace the batch with the networks, each with the sensor list it was decoded with and
+    /// the index of its individual. Recurrent state is copied from the networks, see load_state.
+    /// Buffers are reused, so compiling again allocates only when the batch grows.
+    pub fn compile<'a, I>(&mut self, networks: I)
+    where
+        I: IntoIterator<Item = (&'a Network, &'a [Sensor], usize)>,
+    {
+        let layout = &mut self.layout;
+        let state = &mut self.state;
+
+        layout.neuron_start.clear();
+        layout.sensor_start.clear();
+        layout.sensor_con_start.clear();
+        layout.recurrent_start.clear();
+        layout.hidden_start.clear();
+        layout.action_start.clear();
+        layout.indiv_index.clear();
+        layout.activation.clear();
+        layout.sensors.clear();
+        layout.action_neurons.clear();
+        layout.sensor_cons.clear();
+        layout.recurrent_cons.clear();
+        layout.hidden_cons.clear();
+
+        state.values.clear();
+        state.previous.clear();
+        state.activations.clear();
+
+        for (network, sensor_neurons, indiv_index) in networks {
+            layout.neuron_start.push(layout.activation.len());
+            layout.sensor_start.push(layout.sensors.len());
+            layout.sensor_con_start.push(layout.sensor_cons.len());
+            layout.recurrent_start.push(layout.recurrent_cons.len());
+            layout.hidden_start.push(layout.hidden_cons.len());
+            layout.action_start.push(layout.action_neurons.len());
+            layout.indiv_index.push(indiv_index);
+
+            let sensor_start = layout.sensors.len();
+            for (sensor_index, output, weight) in network.sensor_edges() {
+                let sensor = sensor_neurons[sensor_index];
+                let input = match layout.sensors[sensor_start..].iter().position(|&s| s == sensor) {
+                    Some(input) => input,
+                    None => {
+                        layout.sensors.push(sensor);
+                        layout.sensors.len() - 1 - sensor_start
+                    }
+                };
+                layout.sensor_cons.push(input, output, weight);
+            }
+
+            for (input, output, weight) in network.recurrent_edges() {
+                layout.recurrent_cons.push(input, output, weight);
+            }
+
+            for (input, output, weight) in network.current_edges() {
+                layout.hidden_cons.push(input, output, weight);
+            }
+
+            for i in 0..network.neuron_count() {
+                layout.activation.push(network.neuron_activation(i));
+
+                if let Some(action) = network.neuron_action(i) {
+                    layout.action_neurons.push(i as u32);
+                    state.activations.push(Activation { action, weight: 0.0, indiv_index });
+                }
+            }
+
+            state.previous.extend_from_slice(network.previous_values());
+        }
+
+        layout.neuron_start.push(layout.activation.len());
+        layout.sensor_start.push(layout.sensors.len());
+        layout.sensor_con_start.push(layout.sensor_cons.len());
+        layout.recurrent_start.push(layout.recurrent_cons.len());
+        layout.hidden_start.push(layout.hidden_cons.len());
+        layout.action_start.push(layout.action_neurons.len());
+
+        state.values.resize(layout.activation.len(), 0.0);
+        state.sensor_values.clear();
+        state.sensor_values.resize(layout.sensors.len(), 0.0);
+    }
+
+    /// Put network in slot n instead of the network compiled there, like compile does with the
+    /// whole batch. Only the arrays after the slot move, and only when the size changes.
+    pub fn replace(&mut self, n: usize, network: &Network, sensor_neurons: &[Sensor], indiv_index: usize) {
+        let mut new = NetworkBatch::new();
+        new.compile(std::iter::once((network, sensor_neurons, indiv_index)));
+
+        let layout = &mut self.layout;
+        let state = &mut self.state;
+
+        let neurons = slot(&layout.neuron_start, n);
+        layout.activation.splice(neurons.clone(), new.layout.activation);
+        state.values.splice(neurons.clone(), new.state.values);
+        state.previous.splice(neurons, new.state.previous);
+        shift(&mut layout.neuron_start, n, new.layout.neuron_start[1]);
+
+        let sensors = slot(&layout.sensor_start, n);
+        layout.sensors.splice(sensors.clone(), new.layout.sensors);
+        state.sensor_values.splice(sensors, new.state.sensor_values);
+        shift(&mut layout.sensor_start, n, new.layout.sensor_start[1]);
+
+        layout.sensor_cons.splice(slot(&layout.sensor_con_start, n), &new.layout.sensor_cons);
+        shift(&mut layout.sensor_con_start, n, new.layout.sensor_con_start[1]);
+        layout.recurrent_cons.splice(slot(&layout.recurrent_start, n), &new.layout.recurrent_cons);
+        shift(&mut layout.recurrent_start, n, new.layout.recurrent_start[1]);
+        layout.hidden_cons.splice(slot(&layout.hidden_start, n), &new.layout.hidden_cons);
+        shift(&mut layout.hidden_start, n, new.layout.hidden_start[1]);
+
+        let actions = slot(&layout.action_start, n);
+        layout.action_neurons.splice(actions.clone(), new.layout.action_neurons);
+        state.activations.splice(actions, new.state.activations);
+        shift(&mut layout.action_start, n, new.layout.action_start[1]);
+
+        layout.indiv_index[n] = indiv_index;
+    }
+
+    pub fn len(&self) -> usize {
+        self.layout.indiv_index.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Run all networks one step. Returns the activations of all networks, in order
+    pub fn run(&mut self, world: &World) -> &[Activation] {
+        let layout = &self.layout;
+        let state = &mut self.state;
+
+        let buffers = Buffers {
+            values: &mut state.values,
+            previous: &mut state.previous,
+            sensor_values: &mut state.sensor_values,
+            activations: &mut state.activations,
+        };
+
+        run_networks(layout, 0..layout.indiv_index.len(), buffers, world);
+
+        &self.state.activations
+    }
+
+    /// The activations of the last run of network n
+    pub fn activations(&self, n: usize) -> &[Activation] {
+        &self.state.activations[self.layout.action_start[n]..self.layout.action_start[n + 1]]
+    }
+
+    /// Copy the recurrent state of network n out, so it can be kept over a compile
+    pub fn store_state(&self, n: usize, previous: &mut Vec<f64>) {
+        let neurons = self.layout.neuron_start[n]..self.layout.neuron_start[n + 1];
+        previous.clear();
+        previous.extend_from_slice(&self.state.previous[neurons]);
+    }
+
+    /// Set the recurrent state of network n, as stored by store_state
+    pub fn load_state(&mut self, n: usize, previous: &[f64]) {
+        let neurons = self.layout.neuron_start[n]..self.layout.neuron_start[n + 1];
+        self.state.previous[neurons].copy_from_slice(previous);
+    }
+}
+
+fn run_networks(layout: &Layout, networks: Range<usize>, buffers: Buffers, world: &World) {
+    if networks.len() > MIN_SPLIT {
+        let mid = networks.start + networks.len() / 2;
+        let (left, right) = buffers.split(layout, networks.start, mid);
+        rayon::join(
+            || run_networks(layout, networks.start..mid, left, world),
+            || run_networks(layout, mid..networks.end, right, world),
+        );
+        return;
+    }
+
+    let neuron_base = layout.neuron_start[networks.start];
+    let sensor_base = layout.sensor_start[networks.start];
+    let action_base = layout.action_start[networks.start];
+
+    for n in networks {
+        let neurons = (layout.neuron_start[n] - neuron_base)..(layout.neuron_start[n + 1] - neuron_base);
+        let sensors = (layout.sensor_start[n] - sensor_base)..(layout.sensor_start[n + 1] - sensor_base);
+        let actions = (layout.action_start[n] - action_base)..(layout.action_start[n + 1] - action_base);
+
+        run_network(
+            layout,
+            n,
+            &mut buffers.values[neurons.clone()],
+            &mut buffers.previous[neurons],
+            &mut buffers.sensor_values[sensors],
+            &mut buffers.activations[actions],
+            world,
+        );
+    }
+}
+
+/// Same steps as Network::run, see there
+fn run_network(
+    layout: &Layout,
+    n: usize,
+    values: &mut [f64],
+    previous: &mut [f64],
+    sensor_values: &mut [f64],
+    activations: &mut [Activation],
+    world: &World,
+) {
+    let indiv = &world.individuals[layout.indiv_index[n]];
+    let activation = &layout.activation[layout.neuron_start[n]..layout.neuron_start[n + 1]];
+
+    for value in values.iter_mut() {
+        *value = 0.0;
+    }
+
+    let sensors = &layout.sensors[layout.sensor_start[n]..layout.sensor_start[n + 1]];
+    for (reading, &sensor) in sensor_values.iter_mut().zip(sensors) {
+        *reading = sensor_neurons::get_sensor_input(sensor, world, indiv);
+    }
+
+    let cons = &layout.sensor_cons;
+    for i in layout.sensor_con_start[n]..layout.sensor_con_start[n + 1] {
+        values[cons.output[i] as usize] += sensor_values[cons.input[i] as usize] * cons.weight[i];
+    }
+
+    let cons = &layout.recurrent_cons;
+    let recurrent = layout.recurrent_start[n]..layout.recurrent_start[n + 1];
+    for i in recurrent.clone() {
+        let input = cons.input[i] as usize;
+        values[cons.output[i] as usize] += activation[input].apply(previous[input]) * cons.weight[i];
+    }
+
+    let cons = &layout.hidden_cons;
+    for i in layout.hidden_start[n]..layout.hidden_start[n + 1] {
+        let input = cons.input[i] as usize;
+        values[cons.output[i] as usize] += activation[input].apply(values[input]) * cons.weight[i];
+    }
+
+    if !recurrent.is_empty() {
+        previous.copy_from_slice(values);
+    }
+
+    let action_neurons = &layout.action_neurons[layout.action_start[n]..layout.action_start[n + 1]];
+    for (output, &neuron) in activations.iter_mut().zip(action_neurons) {
+        output.weight = activation[neuron as usize].apply(values[neuron as usize]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::gene_functions;
+    use crate::species::Species;
+
+    #[test]
+    fn same_as_network_run() {
+        let mut rng = rand::thread_rng();
+        let sensor_neurons: Vec<Sensor> = all_sensors().into_iter().filter(|&s| s != Sensor::Random).collect();
+        let action_neurons = all_actions();
+
+        let mut world = World::new(Coord {x: 64, y: 64});
+        for i in 0..1000 {
+            let mut indiv = Individual::new();
+            indiv.grid_index = i * 4;
+            world.add_individual(indiv);
+        }
+
+        let evaluations = [HiddenEvaluation::GenomeOrder, HiddenEvaluation::FeedForward, HiddenEvaluation::Recurrent];
+        let activations = [NeuronActivation::default(), NeuronActivation::Genome];
+
+        let mut networks = vec![];
+        for i in 0..world.individuals.len() {
+            let mut network = Network::empty();
+            let genome = gene_functions::random_genome(&mut rng, 4 + i % 40);
+            let species = Species {
+                sensor_neurons: sensor_neurons.clone(),
+                action_neurons: action_neurons.clone(),
+                hidden_neurons: 3,
+                hidden_evaluation: evaluations[i % 3],
+                activation: activations[i % 2],
+                ..Species::new("test", 0)
+            };
+            network.initialize_from_genome(&genome, &species);
+            network.prune();
+            networks.push(network);
+        }
+
+        let mut batch = NetworkBatch::new();
+        batch.compile(networks.iter().enumerate().map(|(i, n)| (n, &sensor_neurons[..], i)));
+        assert_eq!(1000, batch.len());
+
+        // a few steps, so recurrent state is compared too
+        for _ in 0..3 {
+            let all = batch.run(&world).len();
+            let mut total = 0;
+
+            for (i, network) in networks.iter_mut().enumerate() {
+                let expected = network.run(&sensor_neurons, &world, &world.individuals[i]);
+                let actual = batch.activations(i);
+                total += actual.len();
+
+                assert_eq!(expected.len(), actual.len());
+                for (e, a) in expected.iter().zip(actual) {
+                    assert_eq!(e.action, a.action);
+                    assert_eq!(i, a.indiv_index);
+                    assert_eq!(e.weight, a.weight);
+                }
+            }
+
+            assert_eq!(all, total);
+        }
+    }
+
+    #[test]
+    fn state_survives_compile() {
+        let sensor_neurons = vec![Sensor::Energy];
+        let action_neurons = vec![Action::MoveX];
+
+        // energy -> H0, H0 -> H0 and H0 -> MoveX, see network::tests::self_loop_holds_state
+        let genome = vec![
+            Gene { from_neuron: 0, to_neuron: 0, weight: 10_000 },
+            Gene { from_neuron: 1, to_neuron: 0, weight: 30_000 },
+            Gene { from_neuron: 1, to_neuron: 1, weight: 10_000 },
+        ];
+
+        let mut world = World::new(Coord {x: 16, y: 16});
+        world.add_individual(Individual::new());
+
+        let mut network = Network::empty();
+        let species = Species {
+            sensor_neurons: sensor_neurons.clone(),
+            action_neurons,
+            hidden_neurons: 1,
+            hidden_evaluation: HiddenEvaluation::Recurrent,
+            ..Species::new("test", 0)
+        };
+        network.initialize_from_genome(&genome, &species);
+
+        let mut batch = NetworkBatch::new();
+        batch.compile(vec![(&network, &sensor_neurons[..], 0)]);
+        batch.run(&world);
+
+        world.individuals[0].energy = 0.0;
+        let mut previous = vec![];
+        batch.store_state(0, &mut previous);
+        batch.compile(vec![(&network, &sensor_neurons[..], 0)]);
+        batch.load_state(0, &previous);
+
+        assert!(batch.run(&world)[0].weight > 0.7);
+    }
+
+    #[test]
+    fn replace_same_as_compile() {
+        let mut rng = rand::thread_rng();
+        let sensor_neurons: Vec<Sensor> = all_sensors().into_iter().filter(|&s| s != Sensor::Random).collect();
+
+        let mut world = World::new(Coord {x: 16, y: 16});
+        for i in 0..4 {
+            let mut indiv = Individual::new();
+            indiv.grid_index = i * 3;
+            world.add_individual(indiv);
+        }
+
+        let species = Species {
+            sensor_neurons: sensor_neurons.clone(),
+            action_neurons: all_actions(),
+            hidden_neurons: 3,
+            hidden_evaluation: HiddenEvaluation::Recurrent,
+            ..Species::new("test", 0)
+        };
+        let mut networks: Vec<Network> = [8, 30, 2, 16]
+            .iter()
+            .map(|&len| {
+                let mut network = Network::empty();
+                network.initialize_from_genome(&gene_functions::random_genome(&mut rng, len), &species);
+                network
+            })
+            .collect();
+
+        let mut batch = NetworkBatch::new();
+        batch.compile(networks.iter().enumerate().map(|(i, n)| (n, &sensor_neurons[..], i)));
+
+        // Larger, smaller and last slot
+        for (slot, len) in [(1, 40), (1, 1), (3, 12)] {
+            networks[slot] = Network::empty();
+            networks[slot].initialize_from_genome(&gene_functions::random_genome(&mut rng, len), &species);
+            batch.replace(slot, &networks[slot], &sensor_neurons, slot);
+
+            // The other networks keep the state of the runs before
+            let mut expected = NetworkBatch::new();
+            expected.compile(networks.iter().enumerate().map(|(i, n)| (n, &sensor_neurons[..], i)));
+            let mut previous = vec![];
+            for i in (0..networks.len()).filter(|&i| i != slot) {
+                batch.store_state(i, &mut previous);
+                expected.load_state(i, &previous);
+            }
+
+            for _ in 0..2 {
+                let actual: Vec<(Action, usize, f64)> = batch.run(&world).iter().map(|a| (a.action, a.indiv_index, a.weight)).collect();
+                let expected: Vec<(Action, usize, f64)> = expected.run(&world).iter().map(|a| (a.action, a.indiv_index, a.weight)).collect();
+                assert_eq!(expected, actual);
+            }
+        }
+    }
+}
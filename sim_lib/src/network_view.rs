@@ -0,0 +1,196 @@
+use crate::basic_types::*;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Node {
+    Sensor(Sensor),
+    Hidden(usize),
+    Action(Action),
+}
+
+impl Node {
+    fn name(&self) -> String {
+        match self {
+            Node::Sensor(s) => format!("{:?}", s),
+            Node::Hidden(i) => format!("H{}", i),
+            Node::Action(a) => format!("{:?}", a),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ConnectionView {
+    pub from: Node,
+    pub to: Node,
+    pub weight: f64,
+    pub recurrent: bool, // reads the value of the previous step
+}
+
+/// Read only view of a decoded network, see Network::view
+#[derive(Debug, Clone, PartialEq)]
+pub struct NetworkView {
+    pub sensors: Vec<Sensor>, // sensors with at least one connection
+    pub hidden: Vec<usize>,
+    pub activations: Vec<ActivationFunction>, // of each hidden neuron, in the order of hidden
+    pub actions: Vec<Action>,
+    pub connections: Vec<ConnectionView>, // in evaluation order
+}
+
+impl NetworkView {
+    /// Remove connections and nodes that cannot affect any action, or that never output anything
+    /// but 0. Same rules as Network::prune, so hidden neurons like sigmoid that output without
+    /// any input are kept.
+    pub fn pruned(&self) -> NetworkView {
+        // Nodes that get a value from a sensor, or give one without input
+        let mut fed: Vec<Node> = self.sensors.iter().map(|s| Node::Sensor(*s)).collect();
+        for (hidden, activation) in self.hidden.iter().zip(&self.activations) {
+            if activation.apply(0.0) != 0.0 {
+                fed.push(Node::Hidden(*hidden));
+            }
+        }
+
+        // Nodes with a path to an action
+        let mut useful: Vec<Node> = self.actions.iter().map(|a| Node::Action(*a)).collect();
+
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for con in &self.connections {
+                if fed.contains(&con.from) && !fed.contains(&con.to) {
+                    fed.push(con.to);
+                    changed = true;
+                }
+                if useful.contains(&con.to) && !useful.contains(&con.from) {
+                    useful.push(con.from);
+                    changed = true;
+                }
+            }
+        }
+
+        // Like Network::prune, action neurons are always kept
+        let keep = |node: &Node| matches!(node, Node::Action(_)) || (fed.contains(node) && useful.contains(node));
+
+        let connections: Vec<ConnectionView> = self
+            .connections
+            .iter()
+            .filter(|c| keep(&c.from) && keep(&c.to))
+            .copied()
+            .collect();
+
+        let used = |node: Node| connections.iter().any(|c| c.from == node || c.to == node);
+
+        let (hidden, activations) = self
+            .hidden
+            .iter()
+            .zip(&self.activations)
+            .filter(|(h, _)| used(Node::Hidden(**h)))
+            .unzip();
+
+        NetworkView {
+            sensors: self.sensors.iter().copied().filter(|s| used(Node::Sensor(*s))).collect(),
+            hidden,
+            activations,
+            actions: self.actions.iter().copied().filter(|a| used(Node::Action(*a))).collect(),
+            connections,
+        }
+    }
+
+    /// Graphviz dot. Sensors are boxes, actions double circles. Positive weights are green,
+    /// negative red, and edges get thicker with larger weights. Recurrent edges are dashed.
+    pub fn to_dot(&self) -> String {
+        let mut res = "digraph brain {\n    rankdir=LR;\n".to_string();
+
+        for sensor in &self.sensors {
+            res += &format!("    \"{}\" [shape=box];\n", Node::Sensor(*sensor).name());
+        }
+
+        for hidden in &self.hidden {
+            res += &format!("    \"{}\" [shape=circle];\n", Node::Hidden(*hidden).name());
+        }
+
+        for action in &self.actions {
+            res += &format!("    \"{}\" [shape=doublecircle];\n", Node::Action(*action).name());
+        }
+
+        for con in &self.connections {
+            let color = if con.weight < 0.0 { "red" } else { "darkgreen" };
+            let width = f64::min(0.5 + con.weight.abs(), 5.0);
+            let style = if con.recurrent { ", style=dashed" } else { "" };
+            res += &format!(
+                "    \"{}\" -> \"{}\" [label=\"{:.2}\", color={}, penwidth={:.2}{}];\n",
+                con.from.name(),
+                con.to.name(),
+                con.weight,
+                color,
+                width,
+                style
+            );
+        }
+
+        res += "}\n";
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::network::Network;
+    use crate::species::Species;
+
+    #[test]
+    fn view_and_prune() {
+        let sensor_neurons = vec![Sensor::Constant, Sensor::LocX];
+        let action_neurons = vec![Action::MoveX];
+
+        // inputs: 0, 1 sensors, 2, 3 hidden. outputs: 0, 1 hidden, 2 MoveX
+        let genome = vec![
+            Gene { from_neuron: 0, to_neuron: 2, weight: 10_000 }, // Constant -> MoveX
+            Gene { from_neuron: 1, to_neuron: 0, weight: -5_000 }, // LocX -> H0, dead end
+            Gene { from_neuron: 3, to_neuron: 2, weight: 10_000 }, // H1 -> MoveX, H1 has no input
+        ];
+
+        let mut network = Network::empty();
+        let species = Species {
+            sensor_neurons: sensor_neurons.clone(),
+            action_neurons,
+            hidden_neurons: 2,
+            ..Species::new("test", 0)
+        };
+        network.initialize_from_genome(&genome, &species);
+
+        let view = network.view(&sensor_neurons);
+
+        assert_eq!(vec![Sensor::Constant, Sensor::LocX], view.sensors);
+        assert_eq!(vec![0, 1], view.hidden);
+        assert_eq!(vec![Action::MoveX], view.actions);
+        assert_eq!(3, view.connections.len());
+        assert_eq!(Node::Hidden(0), view.connections[1].to);
+
+        let pruned = view.pruned();
+
+        assert_eq!(vec![Sensor::Constant], pruned.sensors);
+        assert!(pruned.hidden.is_empty());
+        assert_eq!(
+            vec![ConnectionView { from: Node::Sensor(Sensor::Constant), to: Node::Action(Action::MoveX), weight: 1.0, recurrent: false }],
+            pruned.connections
+        );
+
+        let dot = pruned.to_dot();
+        assert!(dot.contains("\"Constant\" -> \"MoveX\" [label=\"1.00\", color=darkgreen, penwidth=1.50];"));
+        assert!(!dot.contains("LocX"));
+
+        // Sigmoid H1 outputs 0.5 without input, so it stays like in Network::prune
+        let species = Species { activation: NeuronActivation::Network(ActivationFunction::Sigmoid), ..species };
+        network.initialize_from_genome(&genome, &species);
+        let pruned = network.view(&sensor_neurons).pruned();
+        assert_eq!(vec![1], pruned.hidden);
+        assert_eq!(vec![ActivationFunction::Sigmoid], pruned.activations);
+        assert_eq!(2, pruned.connections.len());
+
+        network.prune();
+        let view = network.view(&sensor_neurons);
+        assert_eq!(pruned.hidden.len(), view.hidden.len());
+        assert_eq!(pruned.connections.len(), view.connections.len());
+    }
+}
@@ -0,0 +1,285 @@
+use std::collections::HashMap;
+
+use rand::seq::SliceRandom;
+use rand::RngCore;
+
+use crate::basic_types::*;
+use crate::combined_types::*;
+use crate::hooks::PlacementHook;
+use crate::index_functions::*;
+use crate::survival_criteria::CellMask;
+
+/// Built in placement strategies, set with SimulationBuilder::placement.
+/// When a strategy runs out of tiles, the rest of the individuals go on random free tiles.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Placement {
+    /// Anywhere on the grid
+    Random,
+    /// Random tiles in the square within radius of center
+    Cluster { center: Coord, radius: usize },
+    /// Random tiles in the rectangle from min to max, both included
+    Region { min: Coord, max: Coord },
+    /// Random tiles where the mask is set. The mask is stretched to fit the world
+    Mask(CellMask),
+    /// Evenly spaced rows and columns over the whole grid. Does not use the rng
+    Grid,
+    /// Random tiles within radius of where the parent was at the end of the last generation.
+    /// Individuals without a parent in the last generation go anywhere
+    NearParent { radius: usize },
+    /// The tile of the individual with the same index in the last generation, anywhere for the rest
+    SamePosition,
+}
+
+impl Placement {
+    /// Tiles the individuals can go on, for the strategies that pick from a fixed set
+    fn tiles(&self, size: Coord) -> Option<Vec<usize>> {
+        let in_rect = |min: Coord, max: Coord| {
+            let mut tiles = vec![];
+            for y in min.y..=usize::min(max.y, size.y.saturating_sub(1)) {
+                for x in min.x..=usize::min(max.x, size.x.saturating_sub(1)) {
+                    tiles.push(coord_to_index(Coord { x, y }, size));
+                }
+            }
+            tiles
+        };
+
+        match self {
+            Placement::Cluster { center, radius } => Some(in_rect(
+                Coord { x: center.x.saturating_sub(*radius), y: center.y.saturating_sub(*radius) },
+                Coord { x: center.x + radius, y: center.y + radius },
+            )),
+            Placement::Region { min, max } => Some(in_rect(*min, *max)),
+            Placement::Mask(mask) => Some(
+                (0..size.x * size.y)
+                    .filter(|&i| mask.contains(index_to_coord(i, size), size))
+                    .collect(),
+            ),
+            _ => None,
+        }
+    }
+}
+
+impl PlacementHook for Placement {
+    fn place(&self, world: &World, indivs: &mut [Individual], rng: &mut dyn RngCore) {
+        let size = world.grid.size;
+
+        if *self == Placement::Grid {
+            let tiles = grid_spaced(size, indivs.len());
+            for (indiv, grid_index) in indivs.iter_mut().zip(tiles) {
+                indiv.grid_index = grid_index;
+            }
+            return;
+        }
+
+        let mut free = FreeTiles::new(size, rng);
+
+        match self {
+            Placement::NearParent { radius } => {
+                let parent_tiles: HashMap<usize, usize> =
+                    world.individuals.iter().map(|i| (i.id, i.grid_index)).collect();
+
+                for indiv in indivs.iter_mut() {
+                    let near = indiv
+                        .parent
+                        .and_then(|parent| parent_tiles.get(&parent))
+                        .and_then(|&tile| free.near(tile, *radius, rng));
+                    indiv.grid_index = near.unwrap_or_else(|| free.any());
+                }
+                return;
+            }
+            Placement::SamePosition => {
+                let mut rest = vec![];
+                for (i, indiv) in indivs.iter_mut().enumerate() {
+                    match world.individuals.get(i) {
+                        Some(last) if free.take(last.grid_index) => indiv.grid_index = last.grid_index,
+                        _ => rest.push(i),
+                    }
+                }
+                for i in rest {
+                    indivs[i].grid_index = free.any();
+                }
+                return;
+            }
+            _ => {}
+        }
+
+        let mut tiles = self.tiles(size).unwrap_or_default();
+        tiles.shuffle(rng);
+        let mut tiles = tiles.into_iter();
+
+        for indiv in indivs.iter_mut() {
+            indiv.grid_index = match tiles.find(|&t| free.take(t)) {
+                Some(tile) => tile,
+                None => free.any(),
+            };
+        }
+    }
+
+    fn capacity(&self, size: Coord) -> usize {
+        self.tiles(size).map_or(size.x * size.y, |tiles| tiles.len())
+    }
+}
+
+/// Tiles not yet given to an individual of the new generation
+struct FreeTiles {
+    size: Coord,
+    taken: Vec<bool>,
+    shuffled: Vec<usize>, // every tile in random order, popped by any
+}
+
+impl FreeTiles {
+    fn new(size: Coord, rng: &mut dyn RngCore) -> Self {
+        let mut shuffled: Vec<usize> = (0..size.x * size.y).collect();
+        shuffled.shuffle(rng);
+
+        FreeTiles { size, taken: vec![false; size.x * size.y], shuffled }
+    }
+
+    /// Take the tile if it is free
+    fn take(&mut self, tile: usize) -> bool {
+        let free = !self.taken[tile];
+        self.taken[tile] = true;
+        free
+    }
+
+    /// A random free tile. Panics when every tile is taken
+    fn any(&mut self) -> usize {
+        loop {
+            let tile = self.shuffled.pop().expect("More individuals than tiles");
+            if self.take(tile) {
+                return tile;
+            }
+        }
+    }
+
+    /// A random free tile within radius of center
+    fn near(&mut self, center: usize, radius: usize, rng: &mut dyn RngCore) -> Option<usize> {
+        let center = index_to_coord(center, self.size);
+
+        let mut candidates = vec![];
+        for y in center.y.saturating_sub(radius)..=usize::min(center.y + radius, self.size.y - 1) {
+            for x in center.x.saturating_sub(radius)..=usize::min(center.x + radius, self.size.x - 1) {
+                let tile = coord_to_index(Coord { x, y }, self.size);
+                if !self.taken[tile] {
+                    candidates.push(tile);
+                }
+            }
+        }
+
+        let tile = *candidates.choose(rng)?;
+        self.take(tile);
+        Some(tile)
+    }
+}
+
+/// count tiles spread over the grid in rows and columns, as square as the grid allows
+fn grid_spaced(size: Coord, count: usize) -> Vec<usize> {
+    if count == 0 {
+        return vec![];
+    }
+
+    let mut columns = ((count * size.x) as f64 / size.y as f64).sqrt().ceil() as usize;
+    columns = columns.clamp(1, size.x);
+    let mut rows = count.div_ceil(columns);
+    if rows > size.y {
+        rows = size.y;
+        columns = count.div_ceil(rows);
+    }
+
+    // Centers of rows x columns equal cells, at least one tile apart
+    (0..count)
+        .map(|i| {
+            let x = (2 * (i % columns) + 1) * size.x / (2 * columns);
+            let y = (2 * (i / columns) + 1) * size.y / (2 * rows);
+            coord_to_index(Coord { x, y }, size)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn placed(placement: &Placement, world: &World, count: usize) -> Vec<usize> {
+        let mut indivs: Vec<Individual> = (0..count).map(|_| Individual::new()).collect();
+        placement.place(world, &mut indivs, &mut rand::thread_rng());
+
+        let mut tiles: Vec<usize> = indivs.iter().map(|i| i.grid_index).collect();
+        tiles.sort_unstable();
+        tiles.dedup();
+        assert_eq!(count, tiles.len(), "{:?} placed individuals on the same tile", placement);
+        tiles
+    }
+
+    #[test]
+    fn regions() {
+        let size = Coord { x: 32, y: 16 };
+        let world = World::new(size);
+
+        let cluster = Placement::Cluster { center: Coord { x: 1, y: 8 }, radius: 2 };
+        assert_eq!(4 * 5, cluster.capacity(size));
+        for tile in placed(&cluster, &world, 20) {
+            let coord = index_to_coord(tile, size);
+            assert!(coord.x <= 3 && (6..=10).contains(&coord.y));
+        }
+
+        let region = Placement::Region { min: Coord { x: 16, y: 0 }, max: Coord { x: 40, y: 3 } };
+        assert_eq!(16 * 4, region.capacity(size));
+        assert!(placed(&region, &world, 30).iter().all(|&t| index_to_coord(t, size).x >= 16 && t < 32 * 4));
+
+        // Right half of the world
+        let mask = Placement::Mask(CellMask::parse(".#\n").unwrap());
+        assert_eq!(16 * 16, mask.capacity(size));
+        assert!(placed(&mask, &world, 100).iter().all(|&t| index_to_coord(t, size).x >= 16));
+
+        // More than fit go anywhere
+        assert_eq!(40, placed(&cluster, &world, 40).len());
+    }
+
+    #[test]
+    fn grid_spacing() {
+        let size = Coord { x: 32, y: 16 };
+        let world = World::new(size);
+
+        let tiles = placed(&Placement::Grid, &world, 8);
+        let coords: Vec<Coord> = tiles.iter().map(|&t| index_to_coord(t, size)).collect();
+        assert_eq!(
+            vec![(4, 4), (12, 4), (20, 4), (28, 4), (4, 12), (12, 12), (20, 12), (28, 12)],
+            coords.iter().map(|c| (c.x, c.y)).collect::<Vec<_>>()
+        );
+
+        // A full grid uses every tile
+        assert_eq!(32 * 16, placed(&Placement::Grid, &world, 32 * 16).len());
+        assert_eq!(100, placed(&Placement::Grid, &World::new(Coord { x: 100, y: 1 }), 100).len());
+    }
+
+    #[test]
+    fn from_last_generation() {
+        let size = Coord { x: 32, y: 32 };
+        let mut world = World::new(size);
+        for (id, grid_index) in [(7, 100), (8, 500)] {
+            let mut indiv = Individual::new();
+            indiv.id = id;
+            indiv.grid_index = grid_index;
+            world.add_individual(indiv);
+        }
+
+        let mut indivs: Vec<Individual> = (0..10).map(|_| Individual::new()).collect();
+        for indiv in &mut indivs {
+            indiv.parent = Some(8);
+        }
+        indivs[9].parent = None;
+
+        Placement::NearParent { radius: 2 }.place(&world, &mut indivs, &mut rand::thread_rng());
+        for indiv in &indivs[..9] {
+            let (coord, parent) = (index_to_coord(indiv.grid_index, size), index_to_coord(500, size));
+            assert!(coord.x.abs_diff(parent.x) <= 2 && coord.y.abs_diff(parent.y) <= 2);
+        }
+
+        Placement::SamePosition.place(&world, &mut indivs, &mut rand::thread_rng());
+        assert_eq!(100, indivs[0].grid_index);
+        assert_eq!(500, indivs[1].grid_index);
+        assert!(indivs[2..].iter().all(|i| i.grid_index != 100 && i.grid_index != 500));
+    }
+}
@@ -1,23 +1,34 @@
-use rand::Rng;
 use crate::combined_types::*;
 use crate::basic_types::*;
 
+#[cfg(test)]
+thread_local! {
+    /// Every sensor read on this thread, so tests can check which sensors a network reads and how often.
+    pub static READS: std::cell::RefCell<Vec::<Sensor>> = const { std::cell::RefCell::new(Vec::new()) };
+}
+
 
 
+/// Reading of the sensor. Ranges:
+/// LocX, LocY, FoodGradientX, FoodGradientY and Random are -1..1.
+/// WorldBorderDistX, WorldBorderDistY, PheromoneDensity and Energy are 0..1.
+/// BlockedForward is 0 or 1 and Constant is always 1.
 pub fn get_sensor_input(sensor: Sensor, world: &World, indiv: &Individual) -> f64 {
 
+    #[cfg(test)]
+    READS.with(|reads| reads.borrow_mut().push(sensor));
 
     match sensor {
         Sensor::Constant => 1.0,
-        Sensor::Random => {
-            let mut rng = rand::thread_rng();
-            rng.gen_range(-1.0..1.0)
-        },
+        Sensor::Random => random(world, indiv),
         Sensor::LocX => location_x(world, indiv),
         Sensor::LocY => location_y(world, indiv),
         Sensor::WorldBorderDistX => world_border_dist_x(world, indiv),
         Sensor::WorldBorderDistY => world_border_dist_y(world, indiv),
         Sensor::PheromoneDensity => pheromone_density(world, indiv),
+        Sensor::Energy => energy(world, indiv),
+        Sensor::FoodGradientX => food_gradient_x(world, indiv),
+        Sensor::FoodGradientY => food_gradient_y(world, indiv),
         Sensor::BlockedForward => {
             if world.is_dir_empty(indiv.grid_index, indiv.forward) {
                 0.0
@@ -71,6 +82,76 @@ fn pheromone_density(world: &World, indiv: &Individual) -> f64 {
 }
 
 
+// RANDOM
+// Hash of the step seed and the tile, so readings only depend on the simulation rng and
+// networks can read it in parallel. Tiles are unique, so every individual gets its own value
+fn random(world: &World, indiv: &Individual) -> f64 {
+    // splitmix64
+    let mut x = world.random_seed.wrapping_add((indiv.grid_index as u64 + 1).wrapping_mul(0x9E37_79B9_7F4A_7C15));
+    x = (x ^ (x >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    x ^= x >> 31;
+
+    // top 53 bits to -1..1
+    (x >> 11) as f64 / (1u64 << 53) as f64 * 2.0 - 1.0
+}
+
+
+// ENERGY
+// 0 when out of energy, 1 at start energy or above
+fn energy(world: &World, indiv: &Individual) -> f64 {
+    (indiv.energy / world.resources.start_energy).clamp(0.0, 1.0) as f64
+}
+
+
+const FOOD_SENSE_RADIUS: i32 = 5;
+
+// Sum of food in the neighbourhood weighted by the sign of the offset given by sign_fn.
+// Scaled so -1 is all food on the negative side and 1 is all food on the positive side
+fn food_gradient(world: &World, indiv: &Individual, sign_fn: fn(i32, i32) -> i32) -> f64 {
+    let center = crate::index_functions::index_to_coord(indiv.grid_index, world.grid.size);
+
+    let mut total = 0.0;
+    let mut gradient = 0.0;
+
+    for dx in -FOOD_SENSE_RADIUS..=FOOD_SENSE_RADIUS {
+        for dy in -FOOD_SENSE_RADIUS..=FOOD_SENSE_RADIUS {
+            let x = center.x as i32 + dx;
+            let y = center.y as i32 + dy;
+
+            if x < 0 || y < 0 || x >= world.grid.size.x as i32 || y >= world.grid.size.y as i32 {
+                continue;
+            }
+
+            let index = crate::index_functions::coord_to_index(Coord { x: x as usize, y: y as usize }, world.grid.size);
+            let food = world.grid.tiles[index].food as f64;
+
+            total += food;
+            gradient += food * sign_fn(dx, dy) as f64;
+        }
+    }
+
+    if total == 0.0 {
+        return 0.0;
+    }
+
+    gradient / total
+}
+
+// FOOD_GRADIENT_X
+// -1 food is to the left, 1 food is to the right
+fn food_gradient_x(world: &World, indiv: &Individual) -> f64 {
+    food_gradient(world, indiv, |dx, _| dx.signum())
+}
+
+// FOOD_GRADIENT_Y
+// -1 food is down, 1 food is up
+fn food_gradient_y(world: &World, indiv: &Individual) -> f64 {
+    // grid y grows downwards
+    food_gradient(world, indiv, |_, dy| -dy.signum())
+}
+
+
 #[cfg(test)]
 mod tests {
 
@@ -164,5 +245,51 @@ mod tests {
 
     }
 
+    #[test]
+    fn food_gradient_test() {
+
+        let mut world = create_test_world();
+
+        let mut indiv = Individual::new();
+        indiv.grid_index = 64 + 128 * 64;
+
+        assert_eq!(0.0, food_gradient_x(&world, &indiv));
+
+        // food right and up
+        world.grid.tiles[66 + 128 * 62].food = 100;
+
+        assert_eq!(1.0, food_gradient_x(&world, &indiv));
+        assert_eq!(1.0, food_gradient_y(&world, &indiv));
+
+        // same amount on the left, straight above
+        world.grid.tiles[62 + 128 * 64].food = 100;
+
+        assert_eq!(0.0, food_gradient_x(&world, &indiv));
+        assert_eq!(0.5, food_gradient_y(&world, &indiv));
+
+        indiv.energy = 0.5;
+        assert_eq!(0.5, get_sensor_input(Sensor::Energy, &world, &indiv));
+    }
+
+    #[test]
+    fn random_test() {
 
+        let mut world = create_test_world();
+        let mut indiv = Individual::new();
+
+        // Same seed and tile, same reading
+        let first = get_sensor_input(Sensor::Random, &world, &indiv);
+        assert_eq!(first, get_sensor_input(Sensor::Random, &world, &indiv));
+
+        let mut readings = vec![];
+        for step in 0..1000 {
+            world.random_seed = step;
+            indiv.grid_index = step as usize % 7;
+            readings.push(get_sensor_input(Sensor::Random, &world, &indiv));
+        }
+
+        assert!(readings.iter().all(|r| (-1.0..1.0).contains(r)));
+        let mean = readings.iter().sum::<f64>() / readings.len() as f64;
+        assert!(mean.abs() < 0.1);
+    }
 }
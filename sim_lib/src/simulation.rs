@@ -1,35 +1,81 @@
- use rand::seq::SliceRandom;
-use rand::Rng;
-use rayon::prelude::*;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use rand::rngs::StdRng;
+use rand::{Rng, RngCore, SeedableRng};
 
 use crate::action_neurons;
+use crate::analysis;
 use crate::basic_types::*;
 use crate::combined_types::*;
+use crate::diversity;
 use crate::gene_functions;
+use crate::genome_library::{GenomeLibrary, LibrarySeeding};
+use crate::hooks::*;
+use crate::lineage::Lineage;
 use crate::network;
+use crate::network_batch::NetworkBatch;
+use crate::network_view::NetworkView;
+use crate::speciation::Speciation;
+use crate::species::*;
 use crate::survival_criteria as sc;
 
+/// Number of most frequent genomes kept in the diversity stats
+const TOP_GENOMES: usize = 5;
+
 #[derive(Clone)]
 pub struct SimulationBuilder {
     sim: Simulation,
+    sensor_neurons: Vec<Sensor>,
+    action_neurons: Vec<Action>,
 }
 
 impl SimulationBuilder {
     pub fn new(width: usize, height: usize) -> Self {
          Self {
             sim: Simulation::new(width, height),
+            sensor_neurons: default_sensors(),
+            action_neurons: default_actions(),
         }
     }
 
-    pub fn build(mut self) -> Simulation {
+    pub fn build(mut self) -> Result<Simulation, ConfigError> {
+        let size = self.sim.world.grid.size;
+        if size.x == 0 || size.y == 0 {
+            return Err(ConfigError::ZeroWorldSize { width: size.x, height: size.y });
+        }
+
+        let resources = self.sim.config.resources;
+        self.sim.world.resources = resources;
+        self.sim.world.grid.place_food(&mut self.sim.rng, resources.food_tiles);
+
+        self.sim.speciation.threshold = self.sim.config.speciation_threshold;
+
+        if self.sim.species.is_empty() {
+            // Single species from the simulation wide settings
+            let mut species = Species::new("default", self.sim.config.population_size);
+            species.sensor_neurons = self.sensor_neurons;
+            species.action_neurons = self.action_neurons;
+            species.hidden_neurons = self.sim.config.hidden_neurons;
+            species.hidden_evaluation = self.sim.config.hidden_evaluation;
+            species.activation = self.sim.config.activation;
+            species.gene_layout = self.sim.config.gene_layout;
+            species.mutation_rate = self.sim.config.mutation_rate;
+            self.sim.species.push(species);
+        } else {
+            self.sim.config.population_size = self.sim.species.iter().map(|s| s.population_size).sum();
+        }
+
+        self.sim.validate()?;
+
         for i in 0..self.sim.config.population_size {
             self.sim.brains.push(Brain {
                 indiv_index: i,
-                network: network::Network::empty(),
+                network: Arc::new(network::Network::empty()),
             });
         }
 
-        self.sim
+        Ok(self.sim)
     }
 
     pub fn criteria(mut self, c: sc::SurvivalCriteria) -> Self {
@@ -37,6 +83,11 @@ impl SimulationBuilder {
         self
     }
 
+    pub fn criteria_schedule(mut self, schedule: sc::CriteriaSchedule) -> Self {
+        self.sim.config.criteria_schedule = Some(schedule);
+        self
+    }
+
     pub fn population_size(mut self, pop: usize) -> Self {
         self.sim.config.population_size = pop;
         self
@@ -48,7 +99,7 @@ impl SimulationBuilder {
     }
 
     pub fn action_neurons(mut self, an: Vec<Action>) -> Self {
-        self.sim.action_neurons = an;
+        self.action_neurons = an;
         self
     }
 
@@ -57,59 +108,274 @@ impl SimulationBuilder {
         self
     }
 
+    pub fn generation_steps(mut self, steps: usize) -> Self {
+        self.sim.config.generation_steps = steps;
+        self
+    }
+
+    pub fn mode(mut self, mode: EvolutionMode) -> Self {
+        self.sim.config.mode = mode;
+        self
+    }
+
+    pub fn resources(mut self, resources: ResourceConfig) -> Self {
+        self.sim.config.resources = resources;
+        self
+    }
+
+    /// Record analysis::usage in the stats of every interval-th generation, starting with the
+    /// first. It decodes every genome, so it is off by default (0)
+    pub fn usage_interval(mut self, interval: usize) -> Self {
+        self.sim.config.usage_interval = interval;
+        self
+    }
+
+    /// Keep only the lineage of the living, instead of every individual ever born
+    pub fn prune_lineage(mut self, prune: bool) -> Self {
+        self.sim.config.prune_lineage = prune;
+        self
+    }
+
+    pub fn speciation_threshold(mut self, threshold: f64) -> Self {
+        self.sim.config.speciation_threshold = threshold;
+        self
+    }
+
     pub fn hidden_neurons(mut self, hn: usize) -> Self {
         self.sim.config.hidden_neurons = hn;
         self
     }
 
+    pub fn hidden_evaluation(mut self, evaluation: HiddenEvaluation) -> Self {
+        self.sim.config.hidden_evaluation = evaluation;
+        self
+    }
+
+    pub fn activation(mut self, activation: NeuronActivation) -> Self {
+        self.sim.config.activation = activation;
+        self
+    }
+
+    /// Bit widths of the genes, see GeneLayout
+    pub fn gene_layout(mut self, layout: GeneLayout) -> Self {
+        self.sim.config.gene_layout = layout;
+        self
+    }
+
+    /// Seed the random number generator used for genomes, placement, mutation, food, start
+    /// directions and the Random sensor, so runs can be repeated.
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.sim.rng = StdRng::seed_from_u64(seed);
+        self
+    }
+
+    /// Start the first generation from saved genomes instead of random ones. A genome function
+    /// given to initialize_first_generation still takes precedence.
+    pub fn genome_library(mut self, seeding: LibrarySeeding) -> Self {
+        self.sim.library = Some(seeding);
+        self
+    }
+
+    /// Genomes of the first generation, and of species that died out. Default RandomGenome
+    pub fn genome_hook<H: GenomeHook + 'static>(mut self, hook: H) -> Self {
+        self.sim.hooks.genome = Arc::new(hook);
+        self
+    }
+
+    /// Placement of each new generation, e.g. one of placement::Placement. Default RandomPlacement
+    pub fn placement<H: PlacementHook + 'static>(mut self, hook: H) -> Self {
+        self.sim.hooks.placement = Arc::new(hook);
+        self
+    }
+
+    /// Mutation of offspring. Default BitFlipMutation
+    pub fn mutation<H: MutationHook + 'static>(mut self, hook: H) -> Self {
+        self.sim.hooks.mutation = Arc::new(hook);
+        self
+    }
+
+    /// Parent selection. Default UniformSelection
+    pub fn selection<H: SelectionHook + 'static>(mut self, hook: H) -> Self {
+        self.sim.hooks.selection = Arc::new(hook);
+        self
+    }
+
     pub fn sensor_neurons(mut self, sn: Vec<Sensor>) -> Self {
-        self.sim.sensor_neurons = sn;
+        self.sensor_neurons = sn;
+        self
+    }
+
+    /// Add a species to the world. When any species are added, population_size, sensor_neurons,
+    /// action_neurons, hidden_neurons, hidden_evaluation, activation, gene_layout and mutation_rate on the builder are not used.
+    pub fn add_species(mut self, species: Species) -> Self {
+        self.sim.species.push(species);
         self
     }
 }
 
+/// Why SimulationBuilder::build rejected the settings. species is the name of the species
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConfigError {
+    ZeroWorldSize { width: usize, height: usize },
+    EmptyPopulation,
+    /// More individuals than tiles the placement can use
+    PopulationExceedsTiles { population: usize, tiles: usize },
+    ZeroGenomeLength,
+    NoSensors { species: String },
+    NoActions { species: String },
+    /// Mutation rate is not in 0..=1
+    MutationRate { species: String, rate: f32 },
+    GeneLayout { species: String, reason: String },
+    /// Phases or Alternate schedule without criteria, see CriteriaSchedule::validate
+    CriteriaSchedule { reason: String },
+    /// Library random_ratio is not in 0..=1
+    LibraryRandomRatio { ratio: f32 },
+    /// Library mutation_rate is not in 0..=1
+    LibraryMutationRate { rate: f32 },
+    /// Library genome, by index, is not genome_length long
+    LibraryGenomeLength { genome: usize, expected: usize, found: usize },
+    /// Library genome, by index, has a gene that does not fit the gene layout of the species
+    LibraryGenomeLayout { species: String, genome: usize, gene: usize },
+}
+
+impl std::fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ConfigError::ZeroWorldSize { width, height } => write!(f, "world of {}x{} has no tiles", width, height),
+            ConfigError::EmptyPopulation => write!(f, "population is empty"),
+            ConfigError::PopulationExceedsTiles { population, tiles } => {
+                write!(f, "population of {} does not fit on the {} tiles of the placement", population, tiles)
+            }
+            ConfigError::ZeroGenomeLength => write!(f, "genome length is 0"),
+            ConfigError::NoSensors { species } => write!(f, "species {} has no sensor neurons", species),
+            ConfigError::NoActions { species } => write!(f, "species {} has no action neurons", species),
+            ConfigError::MutationRate { species, rate } => {
+                write!(f, "species {} has mutation rate {}, it should be in 0..1", species, rate)
+            }
+            ConfigError::GeneLayout { species, reason } => write!(f, "species {} has an invalid gene layout: {}", species, reason),
+            ConfigError::CriteriaSchedule { reason } => write!(f, "invalid criteria schedule: {}", reason),
+            ConfigError::LibraryRandomRatio { ratio } => write!(f, "library random ratio {} should be in 0..1", ratio),
+            ConfigError::LibraryMutationRate { rate } => write!(f, "library mutation rate {} should be in 0..1", rate),
+            ConfigError::LibraryGenomeLength { genome, expected, found } => {
+                write!(f, "library genome {} has {} genes, expected {}", genome, found, expected)
+            }
+            ConfigError::LibraryGenomeLayout { species, genome, gene } => {
+                write!(f, "gene {} of library genome {} does not fit the gene layout of species {}", gene, genome, species)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
 #[derive(Clone)]
 pub struct Simulation {
     config: Configuration,
     world: World,
     brains: Vec<Brain>,
+    batch: NetworkBatch, // all brains compiled for evaluation, see compile_batch
+    networks: NetworkCache,
 
     generation: usize,
     generation_step: usize,
 
-    rng: rand::rngs::ThreadRng,
-
-    sensor_neurons: Vec<Sensor>,
-    action_neurons: Vec<Action>,
+    rng: StdRng,
 
-    individual_grid_placement_function:
-        fn(&World, &mut Vec<Individual>, &mut rand::rngs::ThreadRng),
+    species: Vec<Species>,
+    library: Option<LibrarySeeding>,
+    hooks: Hooks,
 
     stats: Vec<GenerationStats>,
+    last_step_events: StepEvents,
+    speciation: Speciation,
+    lineage: Lineage,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct GenerationStats {
+    pub survival_rate: f32,
+    pub criteria: Option<sc::SurvivalCriteria>,
+    pub births: usize,
+    pub deaths: usize,
+    pub species: Vec<SpeciesStats>,
+    pub diversity: Option<diversity::DiversityStats>, // measured at the end of the generation
+    pub usage: Option<analysis::UsageStats>, // measured at the end of the generation, see usage_interval
 }
 
 #[derive(Debug, Clone, Copy, Default)]
-struct GenerationStats {
-    survival_rate: f32,
+pub struct StepEvents {
+    pub births: usize,
+    pub deaths: usize,
 }
 
 #[derive(Debug, Clone)]
 struct Brain {
     indiv_index: usize,
-    network: network::Network,
+    network: Arc<network::Network>,
+}
+
+impl Brain {
+    fn initialize(&mut self, network: Arc<network::Network>) {
+        self.network = network;
+    }
 }
 
-fn set_individual_grid_index_random(
-    world: &World,
-    indivs: &mut Vec<Individual>,
-    rng: &mut rand::rngs::ThreadRng,
-) {
-    // Place individuals randomly on the map
-    let mut grid_indicies: Vec<usize> = (0..world.grid.size.x * world.grid.size.y).collect();
-    grid_indicies.shuffle(rng);
+/// Decoded and pruned networks by species and genome, so identical genomes share one network
+/// and only new genomes are decoded
+#[derive(Debug, Clone, Default)]
+struct NetworkCache {
+    species: Vec<HashMap<Genome, Arc<network::Network>>>,
+}
+
+impl NetworkCache {
+    fn get(&mut self, genome: &Genome, species_index: usize, species: &Species) -> Arc<network::Network> {
+        if self.species.len() <= species_index {
+            self.species.resize_with(species_index + 1, Default::default);
+        }
+
+        let networks = &mut self.species[species_index];
+        if let Some(network) = networks.get(genome) {
+            return network.clone();
+        }
 
-    for i in 0..indivs.len() {
-        indivs[i].grid_index = grid_indicies[i];
+        let mut network = network::Network::empty();
+        network.initialize_from_genome(genome, species);
+        network.prune();
+
+        let network = Arc::new(network);
+        networks.insert(genome.clone(), network.clone());
+        network
+    }
+
+    /// Drop networks no brain uses any more
+    fn retain_used(&mut self) {
+        for networks in &mut self.species {
+            networks.retain(|_, network| Arc::strong_count(network) > 1);
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.species.iter().map(|networks| networks.len()).sum()
+    }
+}
+
+/// Extension points, see hooks
+#[derive(Clone)]
+struct Hooks {
+    genome: Arc<dyn GenomeHook>,
+    placement: Arc<dyn PlacementHook>,
+    mutation: Arc<dyn MutationHook>,
+    selection: Arc<dyn SelectionHook>,
+}
+
+impl Default for Hooks {
+    fn default() -> Self {
+        Hooks {
+            genome: Arc::new(RandomGenome),
+            placement: Arc::new(RandomPlacement),
+            mutation: Arc::new(BitFlipMutation),
+            selection: Arc::new(UniformSelection),
+        }
     }
 }
 
@@ -124,50 +390,162 @@ impl Simulation {
                 y: height,
             }),
             brains,
+            batch: NetworkBatch::new(),
+            networks: Default::default(),
             generation: 0,
             generation_step: 0,
-            rng: rand::thread_rng(),
-            individual_grid_placement_function: set_individual_grid_index_random,
+            rng: StdRng::from_entropy(),
+            hooks: Default::default(),
             stats: vec![Default::default()],
-            sensor_neurons: all_sensors(),
-            action_neurons: all_actions(),
+            last_step_events: Default::default(),
+            species: vec![],
+            library: None,
+            speciation: Speciation::new(Configuration::default().speciation_threshold),
+            lineage: Lineage::new(),
+        }
+    }
+
+    /// Check the settings, after the species are set up
+    fn validate(&self) -> Result<(), ConfigError> {
+        let population = self.config.population_size;
+        if population == 0 {
+            return Err(ConfigError::EmptyPopulation);
+        }
+
+        let tiles = self.hooks.placement.capacity(self.world.grid.size);
+        if population > tiles {
+            return Err(ConfigError::PopulationExceedsTiles { population, tiles });
+        }
+
+        if self.config.genome_length == 0 {
+            return Err(ConfigError::ZeroGenomeLength);
+        }
+
+        for species in &self.species {
+            let name = species.name.clone();
+
+            if species.sensor_neurons.is_empty() {
+                return Err(ConfigError::NoSensors { species: name });
+            }
+
+            if species.action_neurons.is_empty() {
+                return Err(ConfigError::NoActions { species: name });
+            }
+
+            if !(0.0..=1.0).contains(&species.mutation_rate) {
+                return Err(ConfigError::MutationRate { species: name, rate: species.mutation_rate });
+            }
+
+            if let Err(reason) = species.gene_layout.validate() {
+                return Err(ConfigError::GeneLayout { species: name, reason });
+            }
+        }
+
+        if let Some(schedule) = &self.config.criteria_schedule {
+            schedule.validate().map_err(|reason| ConfigError::CriteriaSchedule { reason })?;
         }
+
+        if let Some(seeding) = &self.library {
+            self.validate_library(seeding)?;
+        }
+
+        Ok(())
     }
 
-    pub fn initialize_first_generation(
-        &mut self,
-        initial_genome_func: Option<gene_functions::GenomeFunc<rand::rngs::ThreadRng>>,
-    ) {
+    fn validate_library(&self, seeding: &LibrarySeeding) -> Result<(), ConfigError> {
+        if !(0.0..=1.0).contains(&seeding.random_ratio) {
+            return Err(ConfigError::LibraryRandomRatio { ratio: seeding.random_ratio });
+        }
+
+        if !(0.0..=1.0).contains(&seeding.mutation_rate) {
+            return Err(ConfigError::LibraryMutationRate { rate: seeding.mutation_rate });
+        }
+
+        for (index, genome) in seeding.library.genomes.iter().enumerate() {
+            if genome.len() != self.config.genome_length {
+                return Err(ConfigError::LibraryGenomeLength { genome: index, expected: self.config.genome_length, found: genome.len() });
+            }
+
+            // Every species is seeded from the whole library
+            for species in &self.species {
+                let layout = &species.gene_layout;
+                if let Some(gene) = genome.iter().position(|gene| layout.unpack(layout.pack(gene)) != *gene) {
+                    return Err(ConfigError::LibraryGenomeLayout { species: species.name.clone(), genome: index, gene });
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Create and place the first generation. A genome function replaces the genome hook and
+    /// the genome library for this generation only.
+    pub fn initialize_first_generation(&mut self, initial_genome_func: Option<gene_functions::GenomeFunc>) {
+        let (genome_hook, library): (Arc<dyn GenomeHook>, _) = match initial_genome_func {
+            Some(f) => (Arc::new(move |rng: &mut dyn RngCore, _: &Species, genome_len| f(rng, genome_len)), None),
+            None => (self.hooks.genome.clone(), self.library.clone()),
+        };
+
         let mut indivs = vec![];
         // generate individuals
-        for i in 0..self.config.population_size {
-            let genome = match initial_genome_func {
-                Some(f) => f(&mut self.rng, self.config.genome_length),
-                None => gene_functions::random_genome(&mut self.rng, self.config.genome_length),
+        for species_index in 0..self.species.len() {
+            // Library genomes are mutated by the mutation hook, at the rate of the seeding
+            let seeding_species = Species {
+                mutation_rate: library.as_ref().map_or(0.0, |seeding| seeding.mutation_rate),
+                ..self.species[species_index].clone()
             };
-            let mut indiv = Individual::new();
-            indiv.index = i;
-            indiv.genome = genome;
-            indivs.push(indiv);
+            // Each species goes through the library from the start, so all saved genomes are used
+            let mut library_index = 0;
+
+            for _ in 0..self.species[species_index].population_size {
+                let genome = match &library {
+                    Some(seeding) if self.rng.gen::<f32>() >= seeding.random_ratio => {
+                        let genomes = &seeding.library.genomes;
+                        let mut genome = genomes[library_index % genomes.len()].clone();
+                        library_index += 1;
+                        self.hooks.mutation.mutate(&mut self.rng, &seeding_species, &mut genome);
+                        genome
+                    }
+                    _ => genome_hook.create(&mut self.rng, &self.species[species_index], self.config.genome_length),
+                };
+                let mut indiv = Individual::with_rng(&mut self.rng);
+                indiv.index = indivs.len();
+                indiv.genome = genome;
+                indiv.species = species_index;
+                indiv.id = self.lineage.register(None, self.generation, species_index);
+                indivs.push(indiv);
+            }
         }
 
+        self.update_criteria();
         self.setup_individuals(indivs);
+        self.speciation.update(self.generation, &mut self.world.individuals, &self.species);
+    }
+
+    /// Set the criteria for the current generation from the schedule, if any, and record it in stats
+    fn update_criteria(&mut self) {
+        if let Some(schedule) = &self.config.criteria_schedule {
+            self.config.criteria = schedule.criteria_at(self.generation);
+        }
+
+        self.stats[self.generation].criteria = Some(self.config.criteria.clone());
     }
 
     fn setup_individuals(&mut self, mut indivs: Vec<Individual>) {
         for i in 0..indivs.len() {
-            self.brains[i].network.initialize_from_genome(
-                &indivs[i].genome,
-                &self.config,
-                &self.sensor_neurons,
-                &self.action_neurons,
-            );
+            indivs[i].tracker = Default::default();
+            indivs[i].energy = self.config.resources.start_energy;
+            indivs[i].age = 0;
+            let network = self.networks.get(&indivs[i].genome, indivs[i].species, &self.species[indivs[i].species]);
+            self.brains[i].initialize(network);
         }
+        self.networks.retain_used();
 
-        (self.individual_grid_placement_function)(&self.world, &mut indivs, &mut self.rng);
+        self.hooks.placement.place(&self.world, &mut indivs, &mut self.rng);
 
         // Set individuals in the world
         self.world.reset(indivs);
+        self.compile_batch();
     }
 
     pub fn generation(&self) -> usize {
@@ -190,24 +568,141 @@ impl Simulation {
 
         if index == self.generation {
             // Update the generation stats for current gen, to make sure it is computed
-            let survive_indexes = sc::surviving_indexes(&self.world, self.config.criteria);
-            self.stats[self.generation].survival_rate =
-                (survive_indexes.len() as f32 / self.config.population_size as f32) * 100.0;
+            self.update_survival_stats(self.generation);
         }
 
         self.stats[index].survival_rate
     }
 
     pub fn surviving_indexes(&self) -> Vec<usize> {
-        sc::surviving_indexes(&self.world, self.config.criteria)
+        let mut res = vec![];
+        for species_index in 0..self.species.len() {
+            res.extend(self.species_surviving_indexes(species_index));
+        }
+        res.sort_unstable();
+        res
+    }
+
+    pub fn species_surviving_indexes(&self, species_index: usize) -> Vec<usize> {
+        sc::surviving_members(
+            &self.world,
+            self.species_criteria(species_index),
+            &self.species_members(species_index),
+        )
+    }
+
+    /// Genomes of the individuals that survive the current criteria, to seed a later run with.
+    /// Like surviving_indexes, call it when step_single_thread returns true
+    pub fn survivor_library(&self) -> GenomeLibrary {
+        GenomeLibrary {
+            genomes: self.surviving_indexes().into_iter().map(|i| self.world.individuals[i].genome.clone()).collect(),
+        }
+    }
+
+    /// Indexes of all individuals of the species
+    pub fn species_members(&self, species_index: usize) -> Vec<usize> {
+        (0..self.world.individuals.len())
+            .filter(|&i| self.world.individuals[i].species == species_index)
+            .collect()
+    }
+
+    pub fn species_criteria(&self, species_index: usize) -> &sc::SurvivalCriteria {
+        self.species[species_index].criteria.as_ref().unwrap_or(&self.config.criteria)
+    }
+
+    pub fn species(&self) -> &[Species] {
+        &self.species
+    }
+
+    pub fn speciation(&self) -> &Speciation {
+        &self.speciation
+    }
+
+    pub fn lineage(&self) -> &Lineage {
+        &self.lineage
+    }
+
+    /// Most recent common ancestor of everyone currently alive
+    pub fn population_common_ancestor(&self) -> Option<usize> {
+        let ids: Vec<usize> = self.world.individuals.iter().map(|i| i.id).collect();
+        self.lineage.common_ancestor(&ids)
+    }
+
+    fn update_survival_stats(&mut self, generation: usize) {
+        let mut survivors = 0;
+        let mut species_stats = vec![SpeciesStats::default(); self.species.len()];
+
+        for (i, stats) in species_stats.iter_mut().enumerate() {
+            let survive_count = self.species_surviving_indexes(i).len();
+            survivors += survive_count;
+            // A species with no members has no one to survive
+            stats.survival_rate = match self.species[i].population_size {
+                0 => 0.0,
+                size => (survive_count as f32 / size as f32) * 100.0,
+            };
+
+            if let Some(old) = self.stats[generation].species.get(i) {
+                stats.births = old.births;
+                stats.deaths = old.deaths;
+            }
+        }
+
+        self.stats[generation].survival_rate =
+            (survivors as f32 / self.config.population_size as f32) * 100.0;
+        self.stats[generation].species = species_stats;
+    }
+
+    pub fn config(&self) -> &Configuration {
+        &self.config
+    }
+
+    pub fn stats(&self) -> &[GenerationStats] {
+        &self.stats
+    }
+
+    /// Number of distinct decoded networks. Individuals with the same genome share one
+    pub fn decoded_networks(&self) -> usize {
+        self.networks.len()
     }
 
-    pub fn config(&self) -> Configuration {
-        self.config
+    /// The decoded brain of the individual at index in world.individuals, before pruning
+    pub fn network_view(&self, indiv_index: usize) -> NetworkView {
+        let indiv = &self.world.individuals[indiv_index];
+        let species = &self.species[indiv.species];
+        let mut network = network::Network::empty();
+        network.initialize_from_genome(&indiv.genome, species);
+        network.view(&species.sensor_neurons)
+    }
+
+    /// Which sensors, actions and paths the current population uses
+    pub fn usage(&self) -> analysis::UsageStats {
+        analysis::usage(&self.world.individuals, &self.species)
+    }
+
+    /// Diversity stats of all finished generations as csv
+    pub fn diversity_csv(&self) -> String {
+        let mut res = "generation,distinct_genomes,mean_hamming_distance,connection_entropy,top_genome_count\n".to_string();
+
+        for (generation, stats) in self.stats.iter().enumerate() {
+            if let Some(d) = &stats.diversity {
+                let top_count = d.top_genomes.first().map(|t| t.1).unwrap_or(0);
+                res += &format!(
+                    "{},{},{:.3},{:.3},{}\n",
+                    generation, d.distinct_genomes, d.mean_hamming_distance, d.connection_entropy, top_count
+                );
+            }
+        }
+
+        res
+    }
+
+    /// The criteria that was active in the given generation
+    pub fn generation_criteria(&self, generation: usize) -> Option<&sc::SurvivalCriteria> {
+        self.stats.get(generation).and_then(|s| s.criteria.as_ref())
     }
 
     pub fn survive_cells(&self) -> Vec<Coord> {
-        sc::survive_cells(&self.world, self.config.criteria)
+        sc::survive_cells(&self.world, &self.config.criteria)
     }
 
     pub fn run_generation(&mut self) {
@@ -227,61 +722,202 @@ impl Simulation {
             self.generation_step = 0;
 
             // Update the generation stats for current
-            let survive_indexes = sc::surviving_indexes(&self.world, self.config.criteria);
-            self.stats[self.generation - 1].survival_rate =
-                (survive_indexes.len() as f32 / self.config.population_size as f32) * 100.0;
+            self.update_survival_stats(self.generation - 1);
+            self.stats[self.generation - 1].diversity = Some(diversity::measure(
+                &self.world.individuals,
+                &self.species,
+                TOP_GENOMES,
+            ));
+            let interval = self.config.usage_interval;
+            if interval > 0 && (self.generation - 1).is_multiple_of(interval) {
+                self.stats[self.generation - 1].usage = Some(self.usage());
+            }
+            self.stats.push(Default::default());
 
-            let mut new_indivs = vec![];
+            if self.config.mode == EvolutionMode::Generational {
+                self.next_generation_individuals();
+            } else {
+                self.update_criteria();
+                self.speciation.update(self.generation, &mut self.world.individuals, &self.species);
+            }
 
-            for i in 0..self.config.population_size {
-                let index = survive_indexes[self.rng.gen_range(0..survive_indexes.len())];
+            if self.config.prune_lineage {
+                let ids: Vec<usize> = self.world.individuals.iter().map(|i| i.id).collect();
+                self.lineage.prune(&ids);
+            }
+        }
 
-                let mut indiv = Individual::new();
+        // decay pheromones
+        for index in 0..(self.world.grid.size.x * self.world.grid.size.y) {
+             self.world.grid.decrement_pheromone(index, 1);
+        }
 
-                indiv.genome = self.world.individuals[index].genome.clone();
+        self.world.grid.regrow_food(self.config.resources.food_regrow_rate);
 
-                gene_functions::mutate_genome(
-                    &mut self.rng,
-                    self.config.mutation_rate,
-                    &mut indiv.genome,
-                );
+        self.world.random_seed = self.rng.gen();
+        for activation in self.batch.run(&self.world) {
+            action_neurons::perform_action(activation, &mut self.world);
+        }
 
-                indiv.index = i;
+        for i in 0..self.world.individuals.len() {
+            let species = &self.species[self.world.individuals[i].species];
+            let criteria = species.criteria.as_ref().unwrap_or(&self.config.criteria);
+            sc::update_tracker(&mut self.world, i, criteria);
+        }
+
+        for indiv in &mut self.world.individuals {
+            indiv.age += 1;
+        }
+
+        if let EvolutionMode::SteadyState { max_age, criteria_interval, birth_radius } = self.config.mode {
+            self.replace_dead(max_age, criteria_interval, birth_radius);
+        }
+
+        self.generation_step += 1;
+
+        self.generation_step >= self.config.generation_steps
+    }
+
+    fn next_generation_individuals(&mut self) {
+        let mut new_indivs = vec![];
+
+        for species_index in 0..self.species.len() {
+            let survive_indexes = self.species_surviving_indexes(species_index);
+
+            for _ in 0..self.species[species_index].population_size {
+                let mut indiv = Individual::with_rng(&mut self.rng);
+
+                if survive_indexes.is_empty() {
+                    // Species died out, start it over from new genomes
+                    indiv.genome = self.hooks.genome.create(&mut self.rng, &self.species[species_index], self.config.genome_length);
+                } else {
+                    let index = self.hooks.selection.select(&mut self.rng, &self.world, &survive_indexes);
+                    indiv.genome = self.world.individuals[index].genome.clone();
+                    indiv.parent = Some(self.world.individuals[index].id);
+                }
+
+                indiv.id = self.lineage.register(indiv.parent, self.generation, species_index);
+
+                self.hooks.mutation.mutate(&mut self.rng, &self.species[species_index], &mut indiv.genome);
+
+                indiv.index = new_indivs.len();
+                indiv.species = species_index;
 
                 new_indivs.push(indiv);
             }
+        }
+
+        self.update_criteria();
+        self.setup_individuals(new_indivs);
+        self.speciation.update(self.generation, &mut self.world.individuals, &self.species);
+    }
+
+    /// Remove dead individuals and fill their slots with offspring of living individuals.
+    /// Only used in steady state mode.
+    fn replace_dead(&mut self, max_age: usize, criteria_interval: usize, birth_radius: usize) {
+        let check_criteria = criteria_interval > 0 && self.generation_step.is_multiple_of(criteria_interval);
+
+        let mut alive = vec![true; self.world.individuals.len()];
+
+        if check_criteria {
+            alive = vec![false; self.world.individuals.len()];
+            for i in self.surviving_indexes() {
+                alive[i] = true;
+            }
+        }
 
-            self.setup_individuals(new_indivs);
+        for (i, indiv) in self.world.individuals.iter().enumerate() {
+            if indiv.age >= max_age || indiv.energy <= 0.0 {
+                alive[i] = false;
+            }
         }
 
-        // decay pheromones
-        for index in 0..(self.world.grid.size.x * self.world.grid.size.y) {
-             self.world.grid.decrement_pheromone(index, 1);
+        let mut living = vec![vec![]; self.species.len()];
+        for i in (0..alive.len()).filter(|&i| alive[i]) {
+            living[self.world.individuals[i].species].push(i);
         }
 
-        let brains = &mut self.brains;
-        let sensors = &self.sensor_neurons;
-        let world = &self.world;
+        let mut events = StepEvents::default();
+        let mut species_events = vec![StepEvents::default(); self.species.len()];
 
-        let activations: Vec<Vec<Activation>> = brains
-            .par_iter_mut()
-            .map(|brain| {
-                let indiv = &world.individuals[brain.indiv_index];
+        for dead in (0..alive.len()).filter(|&i| !alive[i]) {
+            let species_index = self.world.individuals[dead].species;
 
-                brain.network.run(sensors, world, indiv)
-            })
-            .collect();
+            events.deaths += 1;
+            species_events[species_index].deaths += 1;
 
-        for indiv_activations in &activations {
-            for activation in indiv_activations {
-                action_neurons::perform_action(activation, &mut self.world);
-            }
+            let mut indiv = Individual::with_rng(&mut self.rng);
+            indiv.species = species_index;
+
+            let candidates = &living[species_index];
+            let parent = if candidates.is_empty() {
+                None
+            } else {
+                Some(self.hooks.selection.select(&mut self.rng, &self.world, candidates))
+            };
+
+            // Offspring are born near the parent, a new genome takes the dead one's place
+            let near = match parent {
+                Some(parent) => {
+                    indiv.genome = self.world.individuals[parent].genome.clone();
+                    indiv.cluster = self.world.individuals[parent].cluster;
+                    indiv.parent = Some(self.world.individuals[parent].id);
+                    self.hooks.mutation.mutate(&mut self.rng, &self.species[species_index], &mut indiv.genome);
+                    Some(self.world.individuals[parent].grid_index)
+                }
+                None => {
+                    // Whole species died, start over from new genomes
+                    indiv.genome = self.hooks.genome.create(&mut self.rng, &self.species[species_index], self.config.genome_length);
+                    None
+                }
+            };
+
+            indiv.energy = self.config.resources.start_energy;
+            indiv.id = self.lineage.register(indiv.parent, self.generation, species_index);
+
+            self.world.respawn(dead, indiv, near, birth_radius, &mut self.rng);
+
+            // Reuse the brain slot, and patch only its part of the batch
+            let network = self.networks.get(&self.world.individuals[dead].genome, species_index, &self.species[species_index]);
+            self.batch.replace(dead, &network, &self.species[species_index].sensor_neurons, dead);
+            self.brains[dead].initialize(network);
+
+            events.births += 1;
+            species_events[species_index].births += 1;
         }
 
-        self.generation_step += 1;
-        self.stats.push(Default::default());
+        // Dropping unused networks goes over the whole cache, so only do it once it has grown
+        if self.networks.len() > 2 * self.brains.len() {
+            self.networks.retain_used();
+        }
 
-        self.generation_step >= self.config.generation_steps
+        self.last_step_events = events;
+
+        let stats = &mut self.stats[self.generation];
+        stats.births += events.births;
+        stats.deaths += events.deaths;
+
+        stats.species.resize(self.species.len(), Default::default());
+        for (species_stats, species_events) in stats.species.iter_mut().zip(species_events) {
+            species_stats.births += species_events.births;
+            species_stats.deaths += species_events.deaths;
+        }
+    }
+
+    /// Compile all brains into the batch, when a generation is set up. Single births use NetworkBatch::replace
+    fn compile_batch(&mut self) {
+        let individuals = &self.world.individuals;
+        let species = &self.species;
+
+        self.batch.compile(self.brains.iter().map(|brain| {
+            let sensor_neurons = &species[individuals[brain.indiv_index].species].sensor_neurons;
+            (&*brain.network, &sensor_neurons[..], brain.indiv_index)
+        }));
+    }
+
+    /// Births and deaths in the last step. Always zero in generational mode
+    pub fn last_step_events(&self) -> StepEvents {
+        self.last_step_events
     }
 
     pub fn reset_generation(&mut self) {
@@ -303,7 +939,8 @@ mod tests {
             .hidden_neurons(0)
             .action_neurons(vec![Action::MoveForward])
             .population_size(1)
-            .build();
+            .build()
+            .unwrap();
 
         sim.initialize_first_generation(Some(|rng, genome_len| {
             gene_functions::fixed_genome(rng, genome_len, 0, 0)
@@ -326,4 +963,477 @@ mod tests {
 
          assert_eq!(start + 128, sim.world.individuals[0].grid_index);
     }
+
+    #[test]
+    fn criteria_schedule_per_generation() {
+        let mut sim = SimulationBuilder::new(32, 32)
+            .population_size(50)
+            .criteria_schedule(sc::CriteriaSchedule::Alternate(
+                vec![sc::SurvivalCriteria::LeftPart(0.5), sc::SurvivalCriteria::RightPart(0.5)],
+                1,
+            ))
+            .generation_steps(2)
+            .build()
+            .unwrap();
+
+        sim.initialize_first_generation(None);
+
+        for _ in 0..3 {
+            sim.run_generation();
+        }
+
+        assert_eq!(3, sim.generation());
+        assert!(matches!(sim.generation_criteria(0), Some(sc::SurvivalCriteria::LeftPart(_))));
+        assert!(matches!(sim.generation_criteria(1), Some(sc::SurvivalCriteria::RightPart(_))));
+        assert!(matches!(sim.generation_criteria(2), Some(sc::SurvivalCriteria::LeftPart(_))));
+        assert!(matches!(sim.config().criteria, sc::SurvivalCriteria::RightPart(_)));
+    }
+
+    #[test]
+    fn steady_state_replaces_old() {
+        let mut sim = SimulationBuilder::new(32, 32)
+            .population_size(20)
+            .mode(EvolutionMode::SteadyState {
+                max_age: 5,
+                criteria_interval: 0,
+                birth_radius: 2,
+            })
+            .build()
+            .unwrap();
+
+        sim.initialize_first_generation(None);
+
+        for _ in 0..4 {
+            sim.step_single_thread();
+            assert_eq!(0, sim.last_step_events().deaths);
+        }
+
+        // Everyone reaches max age at the same time
+        sim.step_single_thread();
+        assert_eq!(20, sim.last_step_events().deaths);
+        assert_eq!(20, sim.last_step_events().births);
+
+        assert_eq!(20, sim.population_count());
+        for (i, indiv) in sim.world.individuals.iter().enumerate() {
+            assert_eq!(0, indiv.age);
+            assert_eq!(i, indiv.index);
+            assert_eq!(Some(i), sim.world.grid.tiles[indiv.grid_index].individual_index);
+        }
+
+        let occupied = sim.world.grid.tiles.iter().filter(|t| t.individual_index.is_some()).count();
+        assert_eq!(20, occupied);
+    }
+
+    #[test]
+    fn two_species() {
+        let mut prey = Species::new("prey", 30);
+        prey.action_neurons = vec![Action::MoveX];
+        prey.criteria = Some(sc::SurvivalCriteria::LeftPart(0.5));
+
+        let mut predator = Species::new("predator", 10);
+        predator.sensor_neurons = vec![Sensor::Constant];
+        predator.hidden_neurons = 0;
+
+        let mut sim = SimulationBuilder::new(32, 32)
+            .criteria(sc::SurvivalCriteria::RightPart(0.5))
+            .add_species(prey)
+            .add_species(predator)
+            .add_species(Species::new("extinct", 0))
+            .generation_steps(2)
+            .build()
+            .unwrap();
+
+        sim.initialize_first_generation(None);
+
+        assert_eq!(40, sim.population_count());
+        assert_eq!(30, sim.species_members(0).len());
+        assert_eq!(10, sim.species_members(1).len());
+
+        for i in sim.species_surviving_indexes(0) {
+            let coord = crate::index_functions::index_to_coord(sim.world.individuals[i].grid_index, sim.world.grid.size);
+            assert!(coord.x < 16);
+        }
+
+        // predator uses the simulation criteria
+        for i in sim.species_surviving_indexes(1) {
+            let coord = crate::index_functions::index_to_coord(sim.world.individuals[i].grid_index, sim.world.grid.size);
+            assert!(coord.x >= 16);
+        }
+
+        sim.run_generation();
+        sim.run_generation();
+
+        assert_eq!(30, sim.species_members(0).len());
+        assert_eq!(10, sim.species_members(1).len());
+        assert_eq!(3, sim.stats()[0].species.len());
+        assert_eq!(0.0, sim.stats()[0].species[2].survival_rate);
+    }
+
+    #[test]
+    fn lineage_links_parents() {
+        let mut sim = SimulationBuilder::new(32, 32)
+            .population_size(20)
+            .criteria(sc::SurvivalCriteria::LeftPart(0.5))
+            .generation_steps(1)
+            .build()
+            .unwrap();
+
+        sim.initialize_first_generation(None);
+
+        let first_ids: Vec<usize> = sim.world.individuals.iter().map(|i| i.id).collect();
+        assert_eq!((0..20).collect::<Vec<usize>>(), first_ids);
+
+        sim.run_generation();
+        sim.run_generation();
+
+        assert_eq!(60, sim.lineage().len());
+        for indiv in &sim.world.individuals {
+            let ancestors = sim.lineage().ancestors(indiv.id).unwrap();
+            assert_eq!(2, ancestors.len());
+            assert!(first_ids.contains(&ancestors[1]));
+        }
+    }
+
+    #[test]
+    fn pruned_lineage() {
+        let mut sim = SimulationBuilder::new(32, 32)
+            .population_size(20)
+            .criteria(sc::SurvivalCriteria::LeftPart(0.5))
+            .prune_lineage(true)
+            .generation_steps(1)
+            .build()
+            .unwrap();
+
+        sim.initialize_first_generation(None);
+        for _ in 0..4 {
+            sim.run_generation();
+        }
+
+        // Only the survivors had offspring, so some of every generation before is gone
+        assert!(sim.lineage().len() < 100);
+        for indiv in &sim.world.individuals {
+            assert_eq!(Some(4), sim.lineage().ancestors(indiv.id).map(|a| a.len()));
+        }
+    }
+
+    #[test]
+    fn same_seed_same_run() {
+        let run = || {
+            let mut sim = SimulationBuilder::new(32, 32)
+                .population_size(50)
+                .sensor_neurons(vec![Sensor::Random, Sensor::LocX])
+                .mutation_rate(0.01)
+                .criteria(sc::SurvivalCriteria::LeftPart(0.5))
+                .seed(7)
+                .generation_steps(5)
+                .build()
+                .unwrap();
+
+            sim.initialize_first_generation(None);
+            for _ in 0..3 {
+                sim.run_generation();
+            }
+            sim.world.individuals.iter().map(|i| (i.grid_index, i.forward, i.genome.clone())).collect::<Vec<_>>()
+        };
+
+        assert_eq!(run(), run());
+    }
+
+    #[test]
+    fn sampled_usage() {
+        let mut sim = SimulationBuilder::new(32, 32)
+            .population_size(20)
+            .criteria(sc::SurvivalCriteria::LeftPart(0.5))
+            .usage_interval(2)
+            .generation_steps(1)
+            .build()
+            .unwrap();
+
+        sim.initialize_first_generation(None);
+        for _ in 0..3 {
+            sim.run_generation();
+        }
+
+        let measured: Vec<bool> = sim.stats().iter().map(|s| s.usage.is_some()).collect();
+        assert_eq!(vec![true, false, true, false], measured);
+        assert_eq!(20, sim.stats()[2].usage.as_ref().unwrap().population);
+    }
+
+    #[test]
+    fn speciation_once_per_generation() {
+        let mut sim = SimulationBuilder::new(32, 32)
+            .population_size(20)
+            .criteria(sc::SurvivalCriteria::LeftPart(0.5))
+            .generation_steps(1)
+            .build()
+            .unwrap();
+
+        sim.initialize_first_generation(None);
+        sim.reset_generation();
+        assert_eq!(1, sim.speciation().history().len());
+
+        sim.run_generation();
+        assert_eq!(vec![0, 1], sim.speciation().history().iter().map(|r| r.generation).collect::<Vec<usize>>());
+    }
+
+    #[test]
+    fn identical_genomes_share_network() {
+        let mut sim = SimulationBuilder::new(32, 32)
+            .population_size(50)
+            .criteria(sc::SurvivalCriteria::LeftPart(0.5))
+            .generation_steps(1)
+            .build()
+            .unwrap();
+
+        sim.initialize_first_generation(None);
+        assert_eq!(50, sim.decoded_networks());
+
+        // without mutation the offspring are clones of the survivors
+        sim.run_generation();
+        sim.run_generation();
+
+        let mut genomes: Vec<&Genome> = sim.world.individuals.iter().map(|i| &i.genome).collect();
+        genomes.sort_by_key(|g| g.iter().map(gene_functions::gene_to_bits).collect::<Vec<u32>>());
+        genomes.dedup();
+        assert_eq!(genomes.len(), sim.decoded_networks());
+
+        for (a, b) in sim.brains.iter().zip(sim.brains.iter().skip(1)) {
+            let same_genome = sim.world.individuals[a.indiv_index].genome == sim.world.individuals[b.indiv_index].genome;
+            assert_eq!(same_genome, Arc::ptr_eq(&a.network, &b.network));
+        }
+    }
+
+    #[test]
+    fn seed_from_library() {
+        let library = GenomeLibrary {
+            genomes: vec![
+                vec![Gene { from_neuron: 0, to_neuron: 5, weight: 10_000 }, Gene { from_neuron: 2, to_neuron: 1, weight: 1 }],
+                vec![Gene { from_neuron: 1, to_neuron: 6, weight: -10_000 }, Gene { from_neuron: 3, to_neuron: 0, weight: -1 }],
+            ],
+        };
+
+        let seeded = |library: &GenomeLibrary, random_ratio| {
+            let mut sim = SimulationBuilder::new(32, 32)
+                .population_size(20)
+                .genome_length(2)
+                .criteria(sc::SurvivalCriteria::LeftPart(0.5))
+                .genome_library(LibrarySeeding { library: library.clone(), random_ratio, mutation_rate: 0.0 })
+                .generation_steps(1)
+                .build()
+                .unwrap();
+            sim.initialize_first_generation(None);
+            sim
+        };
+
+        // Every library genome has the length and fits the layout of the species it seeds
+        let fits = |sim: &Simulation, library: &GenomeLibrary| {
+            let layout = &sim.species[0].gene_layout;
+            library.genomes.iter().all(|genome| {
+                genome.len() == sim.config.genome_length && genome.iter().all(|gene| layout.unpack(layout.pack(gene)) == *gene)
+            })
+        };
+
+        // Library genomes in order, without mutation
+        let sim = seeded(&library, 0.0);
+        assert!(fits(&sim, &library));
+        for (i, indiv) in sim.world.individuals.iter().enumerate() {
+            assert_eq!(library.genomes[i % 2], indiv.genome);
+        }
+
+        let sim = seeded(&library, 1.0);
+        assert!(sim.world.individuals.iter().all(|i| !library.genomes.contains(&i.genome)));
+
+        // Survivors of one run seed the next
+        let mut sim = seeded(&library, 0.5);
+        while !sim.step_single_thread() {}
+
+        let survivors = sim.survivor_library();
+        assert_eq!(sim.surviving_indexes().len(), survivors.genomes.len());
+        assert!(!survivors.genomes.is_empty());
+
+        let next = seeded(&survivors, 0.0);
+        assert!(fits(&next, &survivors));
+        for (i, indiv) in next.world.individuals.iter().enumerate() {
+            assert_eq!(survivors.genomes[i % survivors.genomes.len()], indiv.genome);
+        }
+    }
+
+    #[test]
+    fn closure_hooks() {
+        use rand::RngCore;
+
+        let weight = 1234;
+        let mut sim = SimulationBuilder::new(32, 32)
+            .population_size(10)
+            .criteria(sc::SurvivalCriteria::LeftPart(0.5))
+            .genome_hook(move |_rng: &mut dyn RngCore, _species: &Species, len: usize| {
+                vec![Gene { from_neuron: 0, to_neuron: 0, weight }; len]
+            })
+            .placement(|_world: &World, indivs: &mut [Individual], _rng: &mut dyn RngCore| {
+                for (i, indiv) in indivs.iter_mut().enumerate() {
+                    indiv.grid_index = i * 2;
+                }
+            })
+            .mutation(|_rng: &mut dyn RngCore, _species: &Species, genome: &mut Genome| genome[0].weight += 1)
+            .selection(|_rng: &mut dyn RngCore, _world: &World, candidates: &[usize]| candidates[0])
+            .generation_steps(1)
+            .build()
+            .unwrap();
+
+        sim.initialize_first_generation(None);
+
+        for (i, indiv) in sim.world.individuals.iter().enumerate() {
+            assert_eq!(weight, indiv.genome[0].weight);
+            assert_eq!(i * 2, indiv.grid_index);
+        }
+
+        // Sensor to hidden only, so nobody moves and individual 0 survives
+        sim.run_generation();
+
+        for indiv in &sim.world.individuals {
+            assert_eq!(weight + 1, indiv.genome[0].weight);
+            assert_eq!(Some(0), indiv.parent);
+        }
+
+        // Library genomes go through the mutation hook, which sees the rate of the seeding
+        let library = GenomeLibrary { genomes: vec![vec![Gene { from_neuron: 0, to_neuron: 0, weight: 0 }]] };
+        let mut sim = SimulationBuilder::new(32, 32)
+            .population_size(10)
+            .genome_length(1)
+            .genome_library(LibrarySeeding { library, random_ratio: 0.0, mutation_rate: 0.5 })
+            .mutation(|_rng: &mut dyn RngCore, species: &Species, genome: &mut Genome| {
+                genome[0].weight = (species.mutation_rate * 10.0) as i32
+            })
+            .build()
+            .unwrap();
+
+        sim.initialize_first_generation(None);
+        assert!(sim.world.individuals.iter().all(|i| i.genome[0].weight == 5));
+    }
+
+    #[test]
+    fn placement_strategy() {
+        use crate::placement::Placement;
+
+        let region = Placement::Region { min: Coord { x: 0, y: 0 }, max: Coord { x: 7, y: 31 } };
+        let mut sim = SimulationBuilder::new(32, 32)
+            .population_size(50)
+            .placement(region.clone())
+            .build()
+            .unwrap();
+        sim.initialize_first_generation(None);
+
+        assert!(sim.world.individuals.iter().all(|i| i.grid_index % 32 < 8));
+
+        let too_many = SimulationBuilder::new(32, 32).population_size(300).placement(region).build();
+        assert_eq!(Some(ConfigError::PopulationExceedsTiles { population: 300, tiles: 256 }), too_many.err());
+    }
+
+    #[test]
+    fn config_errors() {
+        let error = |builder: SimulationBuilder| builder.build().err();
+
+        assert_eq!(None, error(SimulationBuilder::new(8, 8).population_size(64)));
+
+        assert_eq!(Some(ConfigError::ZeroWorldSize { width: 0, height: 8 }), error(SimulationBuilder::new(0, 8)));
+        assert_eq!(Some(ConfigError::EmptyPopulation), error(SimulationBuilder::new(8, 8).population_size(0)));
+        assert_eq!(
+            Some(ConfigError::PopulationExceedsTiles { population: 65, tiles: 64 }),
+            error(SimulationBuilder::new(8, 8).population_size(65))
+        );
+        // the sum of the species counts
+        assert_eq!(
+            Some(ConfigError::PopulationExceedsTiles { population: 70, tiles: 64 }),
+            error(SimulationBuilder::new(8, 8).add_species(Species::new("a", 40)).add_species(Species::new("b", 30)))
+        );
+        assert_eq!(Some(ConfigError::ZeroGenomeLength), error(SimulationBuilder::new(8, 8).population_size(10).genome_length(0)));
+
+        let species = |name: &str| name.to_string();
+        assert_eq!(
+            Some(ConfigError::NoSensors { species: species("default") }),
+            error(SimulationBuilder::new(8, 8).population_size(10).sensor_neurons(vec![]))
+        );
+        assert_eq!(
+            Some(ConfigError::NoActions { species: species("default") }),
+            error(SimulationBuilder::new(8, 8).population_size(10).action_neurons(vec![]))
+        );
+        assert_eq!(
+            Some(ConfigError::MutationRate { species: species("default"), rate: 1.5 }),
+            error(SimulationBuilder::new(8, 8).population_size(10).mutation_rate(1.5))
+        );
+
+        let mut prey = Species::new("prey", 10);
+        prey.mutation_rate = -0.1;
+        assert_eq!(
+            Some(ConfigError::MutationRate { species: species("prey"), rate: -0.1 }),
+            error(SimulationBuilder::new(8, 8).add_species(prey))
+        );
+
+        let layout = GeneLayout { weight_bits: 0, ..GeneLayout::default() };
+        assert_eq!(
+            Some(ConfigError::GeneLayout { species: species("default"), reason: layout.validate().unwrap_err() }),
+            error(SimulationBuilder::new(8, 8).population_size(10).gene_layout(layout))
+        );
+
+        assert_eq!(
+            Some(ConfigError::CriteriaSchedule { reason: "Phases schedule has no phases".to_string() }),
+            error(SimulationBuilder::new(8, 8).population_size(10).criteria_schedule(sc::CriteriaSchedule::Phases(vec![])))
+        );
+        let empty_alternate = sc::CriteriaSchedule::Alternate(vec![], 10);
+        assert_eq!(
+            Some(ConfigError::CriteriaSchedule { reason: empty_alternate.validate().unwrap_err() }),
+            error(SimulationBuilder::new(8, 8).population_size(10).criteria_schedule(empty_alternate))
+        );
+
+        let gene = |weight| Gene { from_neuron: 0, to_neuron: 0, weight };
+        let seeded = |genomes: Vec<Genome>, random_ratio, mutation_rate| {
+            SimulationBuilder::new(8, 8)
+                .population_size(10)
+                .genome_length(2)
+                .genome_library(LibrarySeeding { library: GenomeLibrary { genomes }, random_ratio, mutation_rate })
+        };
+        assert_eq!(None, error(seeded(vec![vec![gene(1), gene(2)]], 0.5, 0.1)));
+        assert_eq!(Some(ConfigError::LibraryRandomRatio { ratio: 1.5 }), error(seeded(vec![vec![gene(1), gene(2)]], 1.5, 0.0)));
+        assert_eq!(Some(ConfigError::LibraryMutationRate { rate: -0.5 }), error(seeded(vec![vec![gene(1), gene(2)]], 0.0, -0.5)));
+        assert_eq!(
+            Some(ConfigError::LibraryGenomeLength { genome: 1, expected: 2, found: 1 }),
+            error(seeded(vec![vec![gene(1), gene(2)], vec![gene(1)]], 0.0, 0.0))
+        );
+
+        // 1000 needs more than 8 weight bits
+        let layout = GeneLayout { source_index_bits: 12, sink_index_bits: 12, weight_bits: 8, ..GeneLayout::default() };
+        assert_eq!(
+            Some(ConfigError::LibraryGenomeLayout { species: species("default"), genome: 0, gene: 1 }),
+            error(seeded(vec![vec![gene(100), gene(1000)]], 0.0, 0.0).gene_layout(layout))
+        );
+
+        assert_eq!(
+            "world of 0x8 has no tiles",
+            ConfigError::ZeroWorldSize { width: 0, height: 8 }.to_string()
+        );
+    }
+
+    #[test]
+    fn diversity_per_generation() {
+        let mut sim = SimulationBuilder::new(32, 32)
+            .population_size(20)
+            .criteria(sc::SurvivalCriteria::LeftPart(0.5))
+            .generation_steps(1)
+            .build()
+            .unwrap();
+
+        sim.initialize_first_generation(Some(|rng, genome_len| {
+            gene_functions::fixed_genome(rng, genome_len, 0, 0)
+        }));
+
+        sim.run_generation();
+
+        let diversity = sim.stats()[0].diversity.as_ref().unwrap();
+        assert_eq!(1, diversity.distinct_genomes);
+        assert_eq!(0.0, diversity.mean_hamming_distance);
+        assert_eq!(20, diversity.top_genomes[0].1);
+
+        assert!(sim.stats()[1].diversity.is_none());
+        assert_eq!(2, sim.diversity_csv().lines().count());
+    }
 }
@@ -0,0 +1,270 @@
+use crate::basic_types::*;
+use crate::species::Species;
+
+/// Distance between two genomes in 0..1. Genes are aligned on their from and to neuron.
+/// Aligned genes add their weight difference, as a share of the weight range of the layout,
+/// genes without a partner add 1. The sum is divided by the length of the longest genome.
+pub fn genome_distance(a: &Genome, b: &Genome, layout: &GeneLayout) -> f64 {
+    sorted_distance(&sorted_genome(a), &sorted_genome(b), layout)
+}
+
+fn sorted_genome(genome: &Genome) -> Genome {
+    let mut sorted = genome.clone();
+    sorted.sort_by_key(|g| (g.from_neuron, g.to_neuron, g.weight));
+    sorted
+}
+
+/// genome_distance for genomes already sorted by sorted_genome
+fn sorted_distance(a_sorted: &Genome, b_sorted: &Genome, layout: &GeneLayout) -> f64 {
+    let weight_range = 2f64.powi(layout.weight_bits as i32);
+    let len = usize::max(a_sorted.len(), b_sorted.len());
+    if len == 0 {
+        return 0.0;
+    }
+
+    let mut dist = 0.0;
+    let mut i = 0;
+    let mut j = 0;
+
+    while i < a_sorted.len() && j < b_sorted.len() {
+        let a_key = (a_sorted[i].from_neuron, a_sorted[i].to_neuron);
+        let b_key = (b_sorted[j].from_neuron, b_sorted[j].to_neuron);
+
+        if a_key == b_key {
+            let weight_diff = (a_sorted[i].weight as f64 - b_sorted[j].weight as f64).abs();
+            dist += weight_diff / weight_range;
+            i += 1;
+            j += 1;
+        } else if a_key < b_key {
+            dist += 1.0;
+            i += 1;
+        } else {
+            dist += 1.0;
+            j += 1;
+        }
+    }
+
+    dist += (a_sorted.len() - i) as f64 + (b_sorted.len() - j) as f64;
+
+    dist / len as f64
+}
+
+/// Group of similar genomes within one configured species
+#[derive(Debug, Clone)]
+pub struct Cluster {
+    pub id: usize,
+    pub species: usize,
+    pub representative: Genome, // sorted by from neuron, to neuron and weight
+    pub born: usize, // generation
+    pub extinct: Option<usize>, // generation of extinction
+    pub size: usize,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ClusterGeneration {
+    pub generation: usize,
+    pub sizes: Vec<(usize, usize)>, // (cluster id, size)
+    pub born: Vec<usize>,
+    pub extinct: Vec<usize>,
+}
+
+/// Online clustering of the population into genetic species.
+/// Each generation individuals join the first living cluster whose representative is within
+/// threshold, or start a new cluster. Clusters that get no members go extinct.
+#[derive(Debug, Clone)]
+pub struct Speciation {
+    pub threshold: f64,
+    clusters: Vec<Cluster>,
+    history: Vec<ClusterGeneration>,
+}
+
+impl Speciation {
+    pub fn new(threshold: f64) -> Self {
+        Speciation {
+            threshold,
+            clusters: vec![],
+            history: vec![],
+        }
+    }
+
+    /// Assign a cluster id to every individual. species are the configured species the
+    /// individuals belong to, for their gene layouts
+    pub fn update(&mut self, generation: usize, individuals: &mut [Individual], species: &[Species]) {
+        let mut sizes = vec![0; self.clusters.len()];
+        let mut record = ClusterGeneration { generation, ..Default::default() };
+
+        let mut candidates: Vec<usize> = (0..self.clusters.len()).filter(|&c| self.clusters[c].extinct.is_none()).collect();
+
+        for indiv in individuals.iter_mut() {
+            let sorted = sorted_genome(&indiv.genome);
+            let layout = &species[indiv.species].gene_layout;
+            let found = candidates.iter().copied().find(|&c| {
+                self.clusters[c].species == indiv.species
+                    && sorted_distance(&self.clusters[c].representative, &sorted, layout) < self.threshold
+            });
+
+            let cluster = match found {
+                Some(c) => c,
+                None => {
+                    self.clusters.push(Cluster {
+                        id: self.clusters.len(),
+                        species: indiv.species,
+                        representative: sorted,
+                        born: generation,
+                        extinct: None,
+                        size: 0,
+                    });
+                    sizes.push(0);
+                    candidates.push(self.clusters.len() - 1);
+                    record.born.push(self.clusters.len() - 1);
+                    self.clusters.len() - 1
+                }
+            };
+
+            sizes[cluster] += 1;
+            indiv.cluster = cluster;
+        }
+
+        for (c, cluster) in self.clusters.iter_mut().enumerate() {
+            if cluster.extinct.is_some() {
+                continue;
+            }
+
+            cluster.size = sizes[c];
+
+            if sizes[c] == 0 {
+                cluster.extinct = Some(generation);
+                record.extinct.push(c);
+            } else {
+                record.sizes.push((c, sizes[c]));
+            }
+        }
+
+        // Move representatives to a current member, so clusters follow their population
+        let mut moved = vec![false; self.clusters.len()];
+        for indiv in individuals.iter() {
+            if !moved[indiv.cluster] {
+                self.clusters[indiv.cluster].representative = sorted_genome(&indiv.genome);
+                moved[indiv.cluster] = true;
+            }
+        }
+
+        self.history.push(record);
+    }
+
+    pub fn clusters(&self) -> &[Cluster] {
+        &self.clusters
+    }
+
+    pub fn living_clusters(&self) -> impl Iterator<Item = &Cluster> {
+        self.clusters.iter().filter(|c| c.extinct.is_none())
+    }
+
+    pub fn history(&self) -> &[ClusterGeneration] {
+        &self.history
+    }
+
+    /// One line per living cluster per generation: generation,cluster,species,size
+    pub fn to_csv(&self) -> String {
+        let mut res = "generation,cluster,species,size\n".to_string();
+
+        for record in &self.history {
+            for (id, size) in &record.sizes {
+                res += &format!("{},{},{},{}\n", record.generation, id, self.clusters[*id].species, size);
+            }
+        }
+
+        res
+    }
+}
+
+/// A distinct colour for a cluster id. Steps around the colour wheel by the golden angle
+pub fn cluster_color(id: usize) -> (u8, u8, u8) {
+    let hue = (id as f64 * 137.508) % 360.0;
+
+    // hsv to rgb with full saturation and value
+    let x = 1.0 - ((hue / 60.0) % 2.0 - 1.0).abs();
+    let (r, g, b) = match (hue / 60.0) as usize {
+        0 => (1.0, x, 0.0),
+        1 => (x, 1.0, 0.0),
+        2 => (0.0, 1.0, x),
+        3 => (0.0, x, 1.0),
+        4 => (x, 0.0, 1.0),
+        _ => (1.0, 0.0, x),
+    };
+
+    ((r * 255.0) as u8, (g * 255.0) as u8, (b * 255.0) as u8)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn gene(from_neuron: u16, to_neuron: u16, weight: i32) -> Gene {
+        Gene { from_neuron, to_neuron, weight }
+    }
+
+    #[test]
+    fn distance() {
+        let layout = GeneLayout::default();
+        let a = vec![gene(1, 2, 100), gene(3, 4, 0)];
+
+        assert_eq!(0.0, genome_distance(&a, &a, &layout));
+
+        // Order does not matter
+        let b = vec![gene(3, 4, 0), gene(1, 2, 100)];
+        assert_eq!(0.0, genome_distance(&a, &b, &layout));
+
+        // One gene with other endpoints
+        let c = vec![gene(1, 2, 100), gene(5, 4, 0)];
+        assert_eq!(1.0, genome_distance(&a, &c, &layout));
+
+        // Only weight differs
+        let d = vec![gene(1, 2, 100), gene(3, 4, i16::MAX as i32)];
+        assert_relative_eq!(0.25, genome_distance(&a, &d, &layout), epsilon = 0.001);
+
+        // Half the weight range of an 8 bit weight is just as far
+        let narrow = GeneLayout { source_type_bits: 0, source_index_bits: 12, sink_type_bits: 0, sink_index_bits: 12, weight_bits: 8 };
+        let e = vec![gene(1, 2, 100), gene(3, 4, i8::MAX as i32)];
+        assert_relative_eq!(0.25, genome_distance(&a, &e, &narrow), epsilon = 0.002);
+        assert_relative_eq!(genome_distance(&a, &d, &layout), genome_distance(&a, &e, &narrow), epsilon = 0.002);
+    }
+
+    #[test]
+    fn clustering() {
+        let mut speciation = Speciation::new(0.3);
+        let species = vec![Species::new("a", 3), Species::new("b", 0)];
+
+        let genome_a = vec![gene(1, 2, 100), gene(3, 4, 0)];
+        let genome_b = vec![gene(9, 9, 100), gene(8, 8, 0)];
+
+        let mut indivs = vec![];
+        for genome in &[&genome_a, &genome_b, &genome_a] {
+            let mut indiv = Individual::new();
+            indiv.genome = (*genome).clone();
+            indivs.push(indiv);
+        }
+
+        speciation.update(0, &mut indivs, &species);
+
+        assert_eq!(0, indivs[0].cluster);
+        assert_eq!(1, indivs[1].cluster);
+        assert_eq!(0, indivs[2].cluster);
+        assert_eq!(vec![(0, 2), (1, 1)], speciation.history()[0].sizes);
+
+        // b dies out
+        indivs[1].genome = genome_a.clone();
+        speciation.update(1, &mut indivs, &species);
+
+        assert_eq!(vec![1], speciation.history()[1].extinct);
+        assert_eq!(Some(1), speciation.clusters()[1].extinct);
+        assert_eq!(1, speciation.living_clusters().count());
+
+        // Different configured species never share a cluster
+        indivs[2].species = 1;
+        speciation.update(2, &mut indivs, &species);
+        assert_eq!(2, indivs[2].cluster);
+        assert_eq!(vec![2], speciation.history()[2].born);
+    }
+}
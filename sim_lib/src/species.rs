@@ -0,0 +1,97 @@
+use crate::basic_types::*;
+use crate::survival_criteria as sc;
+
+/// A population with its own brain layout, mutation rate and criteria.
+/// Several species can share a world, e.g. for predator/prey setups.
+#[derive(Debug, Clone)]
+pub struct Species {
+    pub name: String,
+    pub population_size: usize,
+    pub sensor_neurons: Vec<Sensor>,
+    pub action_neurons: Vec<Action>,
+    pub hidden_neurons: usize,
+    pub hidden_evaluation: HiddenEvaluation,
+    pub activation: NeuronActivation,
+    pub gene_layout: GeneLayout,
+    pub mutation_rate: f32,
+    // None uses the simulation criteria, including its schedule
+    pub criteria: Option<sc::SurvivalCriteria>,
+    pub color: (u8, u8, u8),
+}
+
+impl Species {
+    pub fn new(name: &str, population_size: usize) -> Self {
+        let config = Configuration::default();
+        Species {
+            name: name.to_string(),
+            population_size,
+            sensor_neurons: default_sensors(),
+            action_neurons: default_actions(),
+            hidden_neurons: config.hidden_neurons,
+            hidden_evaluation: config.hidden_evaluation,
+            activation: config.activation,
+            gene_layout: config.gene_layout,
+            mutation_rate: config.mutation_rate,
+            criteria: None,
+            color: name_color(name),
+        }
+    }
+
+    /// The genes of genome that make connections. With NeuronActivation::Genome the first
+    /// hidden_neurons genes pick the activation functions instead
+    pub fn connection_genes<'a>(&self, genome: &'a [Gene]) -> &'a [Gene] {
+        match self.activation {
+            NeuronActivation::Genome => &genome[usize::min(self.hidden_neurons, genome.len())..],
+            NeuronActivation::Network(_) => genome,
+        }
+    }
+}
+
+/// A bright color picked by a hash of the name, so a species keeps its color between runs
+fn name_color(name: &str) -> (u8, u8, u8) {
+    // FNV-1a, stable unlike the std hashers
+    let hash = name.bytes().fold(0x811c_9dc5_u32, |hash, b| (hash ^ b as u32).wrapping_mul(0x0100_0193));
+
+    // Hue on the color wheel, with fixed saturation and value
+    let hue = (hash % 360) as f32 / 60.0;
+    let (value, saturation) = (0.85, 0.7);
+    let chroma = value * saturation;
+    let x = chroma * (1.0 - (hue % 2.0 - 1.0).abs());
+    let (r, g, b) = match hue as u32 {
+        0 => (chroma, x, 0.0),
+        1 => (x, chroma, 0.0),
+        2 => (0.0, chroma, x),
+        3 => (0.0, x, chroma),
+        4 => (x, 0.0, chroma),
+        _ => (chroma, 0.0, x),
+    };
+
+    let min = value - chroma;
+    let channel = |c: f32| ((c + min) * 255.0).round() as u8;
+    (channel(r), channel(g), channel(b))
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SpeciesStats {
+    pub survival_rate: f32,
+    pub births: usize,
+    pub deaths: usize,
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn color_from_name() {
+        assert_eq!(Species::new("prey", 10).color, Species::new("prey", 20).color);
+        assert_ne!(Species::new("prey", 10).color, Species::new("predator", 10).color);
+
+        // Never black, so species stand out on the grid
+        for name in ["", "a", "default", "predator"] {
+            let (r, g, b) = Species::new(name, 1).color;
+            assert!(r.max(g).max(b) > 200);
+        }
+    }
+}
@@ -2,18 +2,305 @@ use crate::basic_types::*;
 use crate::combined_types::*;
 use crate::index_functions::*;
 
-#[derive(Debug, Clone, Copy)]
+use std::path::Path;
+
+#[derive(Debug, Clone)]
 pub enum SurvivalCriteria {
     TopPart(f32),
     BottomPart(f32),
+    LeftPart(f32),
+    RightPart(f32),
     Border(f32),
+    Corners(f32),
     NoPheromones,
     RequirePheromones,
     PheromoneInterval(u8, u8),
     Center(Coord, u32),
+    Rectangle(Coord, Coord), // top left and bottom right, both inclusive
+    Mask(CellMask),
+
+    // Evaluated over the whole generation, using the individuals tracker
+    StepsIn(Box<SurvivalCriteria>, usize), // at least n steps spent in the zone
+    VisitedCorners(f32), // been in all four corners at some point
+    NeverTouchedPheromones,
+    Travelled(usize), // moved at least n tiles
+
+    // Relative to the rest of the population, ranked over all individuals
+    ClosestTo(Coord, usize), // the n individuals closest to the target
+    MostTravelled(f32), // the top pct of individuals by tiles travelled
+    MinNeighbours(usize, usize), // at least k other individuals within radius r
+
+    MinEnergy(f32),
+
+    And(Box<SurvivalCriteria>, Box<SurvivalCriteria>),
+    Or(Box<SurvivalCriteria>, Box<SurvivalCriteria>),
+    Not(Box<SurvivalCriteria>),
+}
+
+impl SurvivalCriteria {
+    pub fn and(self, other: SurvivalCriteria) -> Self {
+        SurvivalCriteria::And(Box::new(self), Box::new(other))
+    }
+
+    pub fn or(self, other: SurvivalCriteria) -> Self {
+        SurvivalCriteria::Or(Box::new(self), Box::new(other))
+    }
+
+    pub fn steps_in(self, steps: usize) -> Self {
+        SurvivalCriteria::StepsIn(Box::new(self), steps)
+    }
+
+    /// Number of criteria in the tree that need their own counter in the tracker
+    fn counter_count(&self) -> usize {
+        match self {
+            SurvivalCriteria::StepsIn(zone, _) => 1 + zone.counter_count(),
+            SurvivalCriteria::VisitedCorners(_) => 1,
+            SurvivalCriteria::And(a, b) | SurvivalCriteria::Or(a, b) => {
+                a.counter_count() + b.counter_count()
+            }
+            SurvivalCriteria::Not(a) => a.counter_count(),
+            _ => 0,
+        }
+    }
+}
+
+/// Per individual accumulators for criteria that are evaluated over the whole generation.
+/// Reset when a new generation is set up.
+#[derive(Debug, Clone, Default)]
+pub struct Tracker {
+    // One counter per StepsIn and VisitedCorners in the criteria, in depth first order.
+    // StepsIn counts steps, VisitedCorners uses the lower 4 bits as visited flags
+    pub counters: Vec<usize>,
+    pub touched_pheromones: bool,
+    pub tiles_travelled: usize,
+}
+
+impl Tracker {
+    fn counter(&self, index: usize) -> usize {
+        self.counters.get(index).copied().unwrap_or(0)
+    }
+}
+
+/// Update all individuals trackers with their current position. Call once per step.
+pub fn update_trackers(world: &mut World, criteria: &SurvivalCriteria) {
+    for i in 0..world.individuals.len() {
+        update_tracker(world, i, criteria);
+    }
+}
+
+/// Update the tracker of a single individual, for when individuals use different criteria
+pub fn update_tracker(world: &mut World, index: usize, criteria: &SurvivalCriteria) {
+    let mut tracker = std::mem::take(&mut world.individuals[index].tracker);
+    let grid_index = world.individuals[index].grid_index;
+
+    tracker.counters.resize(criteria.counter_count(), 0);
+    tracker.touched_pheromones |= world.grid.tiles[grid_index].pheromone_level > 0;
+
+    update_counters(world, criteria, grid_index, &mut tracker, 0);
+
+    world.individuals[index].tracker = tracker;
+}
+
+fn update_counters(world: &World, criteria: &SurvivalCriteria, grid_index: usize, tracker: &mut Tracker, offset: usize) {
+    match criteria {
+        SurvivalCriteria::StepsIn(zone, _) => {
+            if match_criteria(world, zone, grid_index) == Some(true) {
+                tracker.counters[offset] += 1;
+            }
+            update_counters(world, zone, grid_index, tracker, offset + 1);
+        }
+        SurvivalCriteria::VisitedCorners(pct) => {
+            let coord = index_to_coord(grid_index, world.grid.size);
+            if let Some(corner) = corner_index(world, *pct, coord) {
+                tracker.counters[offset] |= 1 << corner;
+            }
+        }
+        SurvivalCriteria::And(a, b) | SurvivalCriteria::Or(a, b) => {
+            update_counters(world, a, grid_index, tracker, offset);
+            update_counters(world, b, grid_index, tracker, offset + a.counter_count());
+        }
+        SurvivalCriteria::Not(a) => update_counters(world, a, grid_index, tracker, offset),
+        _ => {}
+    }
+}
+
+impl std::ops::Not for SurvivalCriteria {
+    type Output = Self;
+
+    fn not(self) -> Self {
+        SurvivalCriteria::Not(Box::new(self))
+    }
+}
+
+/// A fixed set of surviving cells. The mask is stretched to fit the world,
+/// so a 16x16 mask can be used on a 128x128 world.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CellMask {
+    pub size: Coord,
+    pub cells: Vec<bool>,
+}
+
+impl CellMask {
+    /// Parse a mask from text. One line per row, '#' or '1' marks a surviving cell.
+    /// Every other character is a dead cell. Short lines are padded with dead cells.
+    /// Blank lines before the first and after the last row are skipped, blank lines in between are dead rows.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let lines: Vec<&str> = text.lines().collect();
+        let first = lines.iter().position(|l| !l.trim().is_empty());
+        let last = lines.iter().rposition(|l| !l.trim().is_empty());
+
+        let rows = match (first, last) {
+            (Some(first), Some(last)) => &lines[first..=last],
+            _ => return Err("Mask has no cells".to_string()),
+        };
+
+        let width = rows.iter().map(|r| r.trim_end().chars().count()).max().unwrap_or(0);
+
+        let mut cells = vec![false; width * rows.len()];
+
+        for (y, row) in rows.iter().enumerate() {
+            for (x, c) in row.trim_end().chars().enumerate() {
+                cells[y * width + x] = c == '#' || c == '1';
+            }
+        }
+
+        Ok(CellMask {
+            size: Coord { x: width, y: rows.len() },
+            cells,
+        })
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, String> {
+        let text = std::fs::read_to_string(&path)
+            .map_err(|e| format!("Could not read mask '{}': {}", path.as_ref().display(), e))?;
+
+        Self::parse(&text)
+    }
+
+    /// Whether the cell covering coord of a world of world_size is set
+    pub fn contains(&self, coord: Coord, world_size: Coord) -> bool {
+        let x = coord.x * self.size.x / world_size.x;
+        let y = coord.y * self.size.y / world_size.y;
+
+        self.cells[coord_to_index(Coord { x, y }, self.size)]
+    }
+}
+
+/// Which criteria to use for each generation. Generations given to a nested schedule
+/// are relative to the start of its phase.
+#[derive(Debug, Clone)]
+pub enum CriteriaSchedule {
+    Fixed(SurvivalCriteria),
+    /// (start generation, schedule). Sorted by start generation, first phase should start at 0
+    Phases(Vec<(usize, CriteriaSchedule)>),
+    /// Cycle through the criteria, switching every `period` generations
+    Alternate(Vec<SurvivalCriteria>, usize),
+    /// Move the parameters of `from` towards `to` over the generations start..end.
+    /// If the two criteria are not the same kind, switch halfway instead.
+    Interpolate {
+        from: SurvivalCriteria,
+        to: SurvivalCriteria,
+        start: usize,
+        end: usize,
+    },
+}
+
+impl CriteriaSchedule {
+    /// Phases and Alternate need at least one entry, also in nested schedules
+    pub fn validate(&self) -> Result<(), String> {
+        match self {
+            CriteriaSchedule::Phases(phases) if phases.is_empty() => Err("Phases schedule has no phases".to_string()),
+            CriteriaSchedule::Phases(phases) => phases.iter().try_for_each(|(_, schedule)| schedule.validate()),
+            CriteriaSchedule::Alternate(criterias, _) if criterias.is_empty() => {
+                Err("Alternate schedule has no criteria".to_string())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Panics on schedules that do not validate
+    pub fn criteria_at(&self, generation: usize) -> SurvivalCriteria {
+        match self {
+            CriteriaSchedule::Fixed(c) => c.clone(),
+            CriteriaSchedule::Phases(phases) => {
+                let (start, schedule) = phases
+                    .iter()
+                    .rev()
+                    .find(|(start, _)| *start <= generation)
+                    .unwrap_or(&phases[0]);
+
+                schedule.criteria_at(generation.saturating_sub(*start))
+            }
+            CriteriaSchedule::Alternate(criterias, period) => {
+                let index = (generation / usize::max(1, *period)) % criterias.len();
+                criterias[index].clone()
+            }
+            CriteriaSchedule::Interpolate { from, to, start, end } => {
+                let t = if generation <= *start {
+                    0.0
+                } else if generation >= *end {
+                    1.0
+                } else {
+                    (generation - start) as f32 / (end - start) as f32
+                };
+
+                interpolate(from, to, t)
+            }
+        }
+    }
+}
+
+fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    a + (b - a) * t
+}
+
+fn lerp_usize(a: usize, b: usize, t: f32) -> usize {
+    lerp(a as f32, b as f32, t).round() as usize
+}
+
+fn lerp_coord(a: Coord, b: Coord, t: f32) -> Coord {
+    Coord {
+        x: lerp_usize(a.x, b.x, t),
+        y: lerp_usize(a.y, b.y, t),
+    }
+}
+
+fn interpolate(from: &SurvivalCriteria, to: &SurvivalCriteria, t: f32) -> SurvivalCriteria {
+    use SurvivalCriteria::*;
+
+    match (from, to) {
+        (TopPart(a), TopPart(b)) => TopPart(lerp(*a, *b, t)),
+        (BottomPart(a), BottomPart(b)) => BottomPart(lerp(*a, *b, t)),
+        (LeftPart(a), LeftPart(b)) => LeftPart(lerp(*a, *b, t)),
+        (RightPart(a), RightPart(b)) => RightPart(lerp(*a, *b, t)),
+        (Border(a), Border(b)) => Border(lerp(*a, *b, t)),
+        (Corners(a), Corners(b)) => Corners(lerp(*a, *b, t)),
+        (PheromoneInterval(a_min, a_max), PheromoneInterval(b_min, b_max)) => PheromoneInterval(
+            lerp(*a_min as f32, *b_min as f32, t).round() as u8,
+            lerp(*a_max as f32, *b_max as f32, t).round() as u8,
+        ),
+        (Center(a_center, a_radius), Center(b_center, b_radius)) => Center(
+            lerp_coord(*a_center, *b_center, t),
+            lerp(*a_radius as f32, *b_radius as f32, t).round() as u32,
+        ),
+        (Rectangle(a_tl, a_br), Rectangle(b_tl, b_br)) => {
+            Rectangle(lerp_coord(*a_tl, *b_tl, t), lerp_coord(*a_br, *b_br, t))
+        }
+        _ => {
+            if t < 0.5 {
+                from.clone()
+            } else {
+                to.clone()
+            }
+        }
+    }
 }
 
-pub fn survive_cells(world: &World, criteria: SurvivalCriteria) -> Vec<Coord> {
+/// Cells where an individual survives by its position alone, e.g. for drawing the survival zone.
+/// Travelled and criteria relative to the rest of the population (ClosestTo, MostTravelled,
+/// MinNeighbours, MinEnergy) say nothing about a single cell, so a cell is only included when the
+/// rest of the criteria decides it. And(Center, ClosestTo) gives no cells, Or(Center, ClosestTo) the center.
+pub fn survive_cells(world: &World, criteria: &SurvivalCriteria) -> Vec<Coord> {
     let mut res = vec![];
 
     for x in 0..world.grid.size.x {
@@ -21,7 +308,7 @@ pub fn survive_cells(world: &World, criteria: SurvivalCriteria) -> Vec<Coord> {
             let coord = Coord { x, y };
             let grid_index = coord_to_index(coord, world.grid.size);
 
-            if match_criteria(world, criteria, grid_index) {
+            if match_criteria(world, criteria, grid_index) == Some(true) {
                 res.push(coord)
             }
         }
@@ -30,28 +317,162 @@ pub fn survive_cells(world: &World, criteria: SurvivalCriteria) -> Vec<Coord> {
     res
 }
 
-fn match_criteria(world: &World, criteria: SurvivalCriteria, grid_index: usize) -> bool {
+/// Whether an individual on the cell matches the criteria. None when the cell alone does not
+/// decide it, since the criteria depends on the rest of the population.
+fn match_criteria(world: &World, criteria: &SurvivalCriteria, grid_index: usize) -> Option<bool> {
     let coord = index_to_coord(grid_index, world.grid.size);
 
-    match criteria {
-        SurvivalCriteria::Center(center, radius) => in_center(world, grid_index, center, radius),
+    let matches = match criteria {
+        SurvivalCriteria::Center(center, radius) => in_center(world, grid_index, *center, *radius),
         SurvivalCriteria::NoPheromones => survive_no_pheromones(world, grid_index),
-        SurvivalCriteria::TopPart(pct) => survive_top(world, pct, coord),
-        SurvivalCriteria::Border(pct) => survive_border(world, pct, coord),
-        SurvivalCriteria::BottomPart(pct) => survive_bottom(world, pct, coord),
+        SurvivalCriteria::TopPart(pct) => survive_top(world, *pct, coord),
+        SurvivalCriteria::BottomPart(pct) => survive_bottom(world, *pct, coord),
+        SurvivalCriteria::LeftPart(pct) => survive_left(world, *pct, coord),
+        SurvivalCriteria::RightPart(pct) => survive_right(world, *pct, coord),
+        SurvivalCriteria::Border(pct) => survive_border(world, *pct, coord),
+        SurvivalCriteria::Corners(pct) => survive_corners(world, *pct, coord),
         SurvivalCriteria::RequirePheromones => survive_pheromones(world, grid_index),
         SurvivalCriteria::PheromoneInterval(min, max) => {
-            pheromone_interval(world, grid_index, min, max)
+            pheromone_interval(world, grid_index, *min, *max)
+        }
+        SurvivalCriteria::Rectangle(top_left, bottom_right) => {
+            in_rectangle(*top_left, *bottom_right, coord)
         }
+        SurvivalCriteria::Mask(mask) => mask.contains(coord, world.grid.size),
+        // For criteria evaluated over the generation, a cell matches if it can count towards survival
+        SurvivalCriteria::StepsIn(zone, _) => return match_criteria(world, zone, grid_index),
+        SurvivalCriteria::VisitedCorners(pct) => corner_index(world, *pct, coord).is_some(),
+        SurvivalCriteria::NeverTouchedPheromones => survive_no_pheromones(world, grid_index),
+        // Distance travelled has nothing to do with the cell, and relative criteria depend on the
+        // rest of the population
+        SurvivalCriteria::Travelled(_)
+        | SurvivalCriteria::ClosestTo(..)
+        | SurvivalCriteria::MostTravelled(_)
+        | SurvivalCriteria::MinNeighbours(..)
+        | SurvivalCriteria::MinEnergy(_) => return None,
+        // Decided when one side decides it, like false && x
+        SurvivalCriteria::And(a, b) => {
+            return match (match_criteria(world, a, grid_index), match_criteria(world, b, grid_index)) {
+                (Some(false), _) | (_, Some(false)) => Some(false),
+                (Some(true), Some(true)) => Some(true),
+                _ => None,
+            };
+        }
+        SurvivalCriteria::Or(a, b) => {
+            return match (match_criteria(world, a, grid_index), match_criteria(world, b, grid_index)) {
+                (Some(true), _) | (_, Some(true)) => Some(true),
+                (Some(false), Some(false)) => Some(false),
+                _ => None,
+            };
+        }
+        SurvivalCriteria::Not(a) => return match_criteria(world, a, grid_index).map(|a| !a),
+    };
+
+    Some(matches)
+}
+
+/// Evaluate the criteria for every member. Members are indexes into world.individuals and
+/// the result has a flag per member. Criteria are evaluated as sets over all members, so
+/// relative criteria can rank members against each other.
+fn evaluate(world: &World, criteria: &SurvivalCriteria, members: &[usize], offset: usize) -> Vec<bool> {
+    let indivs = || members.iter().map(|&i| &world.individuals[i]);
+
+    match criteria {
+        SurvivalCriteria::StepsIn(_, steps) => {
+            indivs().map(|i| i.tracker.counter(offset) >= *steps).collect()
+        }
+        SurvivalCriteria::VisitedCorners(_) => {
+            indivs().map(|i| i.tracker.counter(offset) & 0b1111 == 0b1111).collect()
+        }
+        SurvivalCriteria::NeverTouchedPheromones => {
+            indivs().map(|i| !i.tracker.touched_pheromones).collect()
+        }
+        SurvivalCriteria::Travelled(tiles) => {
+            indivs().map(|i| i.tracker.tiles_travelled >= *tiles).collect()
+        }
+        SurvivalCriteria::ClosestTo(target, n) => closest_to(world, members, *target, *n),
+        SurvivalCriteria::MostTravelled(pct) => most_travelled(world, members, *pct),
+        SurvivalCriteria::MinNeighbours(k, radius) => {
+            indivs().map(|i| neighbour_count(world, i.grid_index, *radius) >= *k).collect()
+        }
+        SurvivalCriteria::MinEnergy(min) => indivs().map(|i| i.energy >= *min).collect(),
+        SurvivalCriteria::And(a, b) => {
+            let b_res = evaluate(world, b, members, offset + a.counter_count());
+            evaluate(world, a, members, offset).iter().zip(b_res).map(|(a, b)| *a && b).collect()
+        }
+        SurvivalCriteria::Or(a, b) => {
+            let b_res = evaluate(world, b, members, offset + a.counter_count());
+            evaluate(world, a, members, offset).iter().zip(b_res).map(|(a, b)| *a || b).collect()
+        }
+        SurvivalCriteria::Not(a) => evaluate(world, a, members, offset).iter().map(|a| !a).collect(),
+        // Only cell criteria are left, and those always decide
+        _ => indivs().map(|i| match_criteria(world, criteria, i.grid_index) == Some(true)).collect(),
     }
 }
 
-pub fn surviving_indexes(world: &World, criteria: SurvivalCriteria) -> Vec<usize> {
+/// Mark the first n of the ranking as surviving
+fn take_ranked(len: usize, ranking: &[usize], n: usize) -> Vec<bool> {
+    let mut res = vec![false; len];
+
+    for &i in ranking.iter().take(n) {
+        res[i] = true;
+    }
+
+    res
+}
+
+fn closest_to(world: &World, members: &[usize], target: Coord, n: usize) -> Vec<bool> {
+    let dist_sqr = |grid_index: usize| {
+        let coord = index_to_coord(grid_index, world.grid.size);
+        let x = coord.x as i64 - target.x as i64;
+        let y = coord.y as i64 - target.y as i64;
+        x * x + y * y
+    };
+
+    let mut ranking: Vec<usize> = (0..members.len()).collect();
+    ranking.sort_by_key(|&i| dist_sqr(world.individuals[members[i]].grid_index));
+
+    take_ranked(ranking.len(), &ranking, n)
+}
+
+fn most_travelled(world: &World, members: &[usize], pct: f32) -> Vec<bool> {
+    let mut ranking: Vec<usize> = (0..members.len()).collect();
+    ranking.sort_by_key(|&i| std::cmp::Reverse(world.individuals[members[i]].tracker.tiles_travelled));
+
+    let n = (ranking.len() as f32 * pct).round() as usize;
+
+    take_ranked(ranking.len(), &ranking, n)
+}
+
+fn neighbour_count(world: &World, grid_index: usize, radius: usize) -> usize {
+    let center = index_to_coord(grid_index, world.grid.size);
+    let mut count = 0;
+
+    for x in center.x.saturating_sub(radius)..=usize::min(center.x + radius, world.grid.size.x - 1) {
+        for y in center.y.saturating_sub(radius)..=usize::min(center.y + radius, world.grid.size.y - 1) {
+            let index = coord_to_index(Coord { x, y }, world.grid.size);
+            if index != grid_index && world.grid.tiles[index].individual_index.is_some() {
+                count += 1;
+            }
+        }
+    }
+
+    count
+}
+
+pub fn surviving_indexes(world: &World, criteria: &SurvivalCriteria) -> Vec<usize> {
+    let members: Vec<usize> = (0..world.individuals.len()).collect();
+    surviving_members(world, criteria, &members)
+}
+
+/// Like surviving_indexes, but only for the given members. Relative criteria rank the members
+/// against each other and ignore everyone else.
+pub fn surviving_members(world: &World, criteria: &SurvivalCriteria, members: &[usize]) -> Vec<usize> {
     let mut res = vec![];
 
-    for indiv in &world.individuals {
-        if match_criteria(world, criteria, indiv.grid_index) {
-            res.push(indiv.index);
+    for (&i, survive) in members.iter().zip(evaluate(world, criteria, members, 0)) {
+        if survive {
+            res.push(world.individuals[i].index);
         }
     }
 
@@ -100,6 +521,49 @@ fn survive_bottom(world: &World, pct: f32, coord: Coord) -> bool {
     coord.y > min_survive_y
 }
 
+fn survive_left(world: &World, pct: f32, coord: Coord) -> bool {
+    let max_survive_x = (world.grid.size.x as f32 * pct) as usize;
+    coord.x < max_survive_x
+}
+
+fn survive_right(world: &World, pct: f32, coord: Coord) -> bool {
+    let min_survive_x = world.grid.size.x - (world.grid.size.x as f32 * pct) as usize;
+    coord.x >= min_survive_x
+}
+
+fn survive_corners(world: &World, pct: f32, coord: Coord) -> bool {
+    corner_index(world, pct, coord).is_some()
+}
+
+/// 0 top left, 1 top right, 2 bottom left, 3 bottom right
+fn corner_index(world: &World, pct: f32, coord: Coord) -> Option<usize> {
+    let w = (world.grid.size.x as f32 * pct) as usize;
+    let h = (world.grid.size.y as f32 * pct) as usize;
+
+    let x = if coord.x < w {
+        0
+    } else if coord.x >= world.grid.size.x - w {
+        1
+    } else {
+        return None;
+    };
+
+    let y = if coord.y < h {
+        0
+    } else if coord.y >= world.grid.size.y - h {
+        2
+    } else {
+        return None;
+    };
+
+    Some(x + y)
+}
+
+fn in_rectangle(top_left: Coord, bottom_right: Coord, coord: Coord) -> bool {
+    coord.x >= top_left.x && coord.x <= bottom_right.x
+        && coord.y >= top_left.y && coord.y <= bottom_right.y
+}
+
 #[cfg(test)]
 mod tests {
 
@@ -114,7 +578,7 @@ mod tests {
 
         world.add_individual(indiv);
 
-        let indexes = surviving_indexes(&world, SurvivalCriteria::TopPart(0.1));
+        let indexes = surviving_indexes(&world, &SurvivalCriteria::TopPart(0.1));
 
         assert_eq!(0, indexes.len());
 
@@ -134,7 +598,7 @@ mod tests {
 
         world.add_individual(indiv);
 
-        let indexes = surviving_indexes(&world, SurvivalCriteria::TopPart(0.1));
+        let indexes = surviving_indexes(&world, &SurvivalCriteria::TopPart(0.1));
 
         assert_eq!(1, indexes.len())
     }
@@ -158,4 +622,237 @@ mod tests {
 
         assert_eq!(true, survive_border(&world, 0.02, Coord { x: 64, y: 126 }));
     }
+
+    #[test]
+    fn survive_corners_test() {
+        let world = World::new(Coord { x: 100, y: 100 });
+
+        assert!(survive_corners(&world, 0.1, Coord { x: 0, y: 0 }));
+        assert!(survive_corners(&world, 0.1, Coord { x: 99, y: 5 }));
+        assert!(survive_corners(&world, 0.1, Coord { x: 90, y: 90 }));
+        assert!(!survive_corners(&world, 0.1, Coord { x: 50, y: 0 }));
+        assert!(!survive_corners(&world, 0.1, Coord { x: 0, y: 10 }));
+    }
+
+    #[test]
+    fn combined_criteria() {
+        let mut world = World::new(Coord { x: 100, y: 100 });
+
+        // top left, with pheromones
+        let mut indiv = Individual::new();
+        indiv.grid_index = 0;
+        world.add_individual(indiv);
+        world.grid.increment_pheromone(0, 20);
+
+        // top right, without pheromones
+        let mut indiv = Individual::new();
+        indiv.grid_index = 99;
+        world.add_individual(indiv);
+
+        // middle
+        let mut indiv = Individual::new();
+        indiv.grid_index = 50 * 100 + 50;
+        world.add_individual(indiv);
+
+        let top_and_pheromones = SurvivalCriteria::TopPart(0.2).and(SurvivalCriteria::RequirePheromones);
+        assert_eq!(vec![0], surviving_indexes(&world, &top_and_pheromones));
+
+        let left_or_right = SurvivalCriteria::LeftPart(0.1).or(SurvivalCriteria::RightPart(0.1));
+        assert_eq!(vec![0, 1], surviving_indexes(&world, &left_or_right));
+
+        assert_eq!(vec![2], surviving_indexes(&world, &!left_or_right));
+
+        let rect = SurvivalCriteria::Rectangle(Coord { x: 40, y: 40 }, Coord { x: 50, y: 50 });
+        assert_eq!(vec![2], surviving_indexes(&world, &rect));
+        assert_eq!(121, survive_cells(&world, &rect).len());
+    }
+
+    #[test]
+    fn mask_criteria() {
+        let mask = CellMask::parse("#.\n.#\n").unwrap();
+
+        assert_eq!(Coord { x: 2, y: 2 }, mask.size);
+
+        let world = World::new(Coord { x: 10, y: 10 });
+
+        let cells = survive_cells(&world, &SurvivalCriteria::Mask(mask));
+
+        // the mask is stretched, so each mask cell covers 5x5 world cells
+        assert_eq!(50, cells.len());
+        assert!(cells.contains(&Coord { x: 4, y: 4 }));
+        assert!(cells.contains(&Coord { x: 9, y: 9 }));
+        assert!(!cells.contains(&Coord { x: 5, y: 4 }));
+
+        assert!(CellMask::parse("\n\n").is_err());
+
+        // Blank rows in the middle are dead rows, blank lines around the mask are skipped
+        let mask = CellMask::parse("\n\n#\n\n  \n#\n\n").unwrap();
+        assert_eq!(Coord { x: 1, y: 4 }, mask.size);
+        assert_eq!(vec![true, false, false, true], mask.cells);
+    }
+
+    #[test]
+    fn schedule_phases() {
+        let center = Coord { x: 64, y: 64 };
+        let schedule = CriteriaSchedule::Phases(vec![
+            (0, CriteriaSchedule::Fixed(SurvivalCriteria::Border(0.1))),
+            (50, CriteriaSchedule::Fixed(SurvivalCriteria::Center(center, 10))),
+            (
+                100,
+                CriteriaSchedule::Alternate(
+                    vec![SurvivalCriteria::TopPart(0.2), SurvivalCriteria::BottomPart(0.2)],
+                    20,
+                ),
+            ),
+        ]);
+
+        assert!(matches!(schedule.criteria_at(0), SurvivalCriteria::Border(_)));
+        assert!(matches!(schedule.criteria_at(49), SurvivalCriteria::Border(_)));
+        assert!(matches!(schedule.criteria_at(50), SurvivalCriteria::Center(_, 10)));
+        assert!(matches!(schedule.criteria_at(119), SurvivalCriteria::TopPart(_)));
+        assert!(matches!(schedule.criteria_at(120), SurvivalCriteria::BottomPart(_)));
+        assert!(matches!(schedule.criteria_at(140), SurvivalCriteria::TopPart(_)));
+    }
+
+    #[test]
+    fn empty_schedules() {
+        assert!(CriteriaSchedule::Phases(vec![]).validate().is_err());
+        assert!(CriteriaSchedule::Alternate(vec![], 10).validate().is_err());
+
+        // nested in a phase
+        let nested = CriteriaSchedule::Phases(vec![
+            (0, CriteriaSchedule::Fixed(SurvivalCriteria::Border(0.1))),
+            (10, CriteriaSchedule::Alternate(vec![], 10)),
+        ]);
+        assert!(nested.validate().is_err());
+
+        let alternate = CriteriaSchedule::Alternate(vec![SurvivalCriteria::Border(0.1)], 0);
+        assert!(alternate.validate().is_ok());
+        assert!(matches!(alternate.criteria_at(3), SurvivalCriteria::Border(_)));
+    }
+
+    #[test]
+    fn schedule_interpolate() {
+        let schedule = CriteriaSchedule::Interpolate {
+            from: SurvivalCriteria::Border(0.2),
+            to: SurvivalCriteria::Border(0.1),
+            start: 10,
+            end: 20,
+        };
+
+        match schedule.criteria_at(15) {
+            SurvivalCriteria::Border(pct) => assert_relative_eq!(0.15, pct),
+            c => panic!("Expected border got {:?}", c),
+        }
+
+        match schedule.criteria_at(100) {
+            SurvivalCriteria::Border(pct) => assert_relative_eq!(0.1, pct),
+            c => panic!("Expected border got {:?}", c),
+        }
+
+        // Different kinds switch halfway
+        let schedule = CriteriaSchedule::Interpolate {
+            from: SurvivalCriteria::Border(0.2),
+            to: SurvivalCriteria::NoPheromones,
+            start: 0,
+            end: 10,
+        };
+
+        assert!(matches!(schedule.criteria_at(4), SurvivalCriteria::Border(_)));
+        assert!(matches!(schedule.criteria_at(5), SurvivalCriteria::NoPheromones));
+    }
+
+    #[test]
+    fn steps_in_zone() {
+        let mut world = World::new(Coord { x: 10, y: 10 });
+
+        let mut indiv = Individual::new();
+        indiv.grid_index = 0;
+        indiv.forward = Dir::Right;
+        world.add_individual(indiv);
+
+        let criteria = SurvivalCriteria::LeftPart(0.3).steps_in(3);
+
+        // x = 0, 1, 2 are in the zone, x = 3 is not
+        update_trackers(&mut world, &criteria);
+        for _ in 0..3 {
+            world.move_indiv_dir(0, Dir::Right);
+            update_trackers(&mut world, &criteria);
+        }
+
+        assert_eq!(3, world.individuals[0].tracker.counters[0]);
+        assert_eq!(3, world.individuals[0].tracker.tiles_travelled);
+
+        // The final tile is outside the zone, but enough steps were spent in it
+        assert_eq!(0, surviving_indexes(&world, &SurvivalCriteria::LeftPart(0.3)).len());
+        assert_eq!(vec![0], surviving_indexes(&world, &criteria));
+        assert_eq!(vec![0], surviving_indexes(&world, &SurvivalCriteria::Travelled(3)));
+        assert_eq!(0, surviving_indexes(&world, &SurvivalCriteria::Travelled(4)).len());
+
+        // The zone of StepsIn is drawn, but travel does not depend on the cell
+        assert_eq!(30, survive_cells(&world, &criteria).len());
+        assert!(survive_cells(&world, &SurvivalCriteria::Travelled(3)).is_empty());
+        assert!(survive_cells(&world, &!SurvivalCriteria::Travelled(3)).is_empty());
+    }
+
+    #[test]
+    fn visited_corners_and_pheromones() {
+        let mut world = World::new(Coord { x: 10, y: 10 });
+
+        let mut indiv = Individual::new();
+        indiv.grid_index = 0;
+        world.add_individual(indiv);
+
+        let corners = SurvivalCriteria::VisitedCorners(0.1);
+        let criteria = SurvivalCriteria::NeverTouchedPheromones.and(corners.clone());
+
+        for &grid_index in &[0, 9, 90, 99] {
+            world.reset(vec![Individual { grid_index, ..world.individuals[0].clone() }]);
+            update_trackers(&mut world, &criteria);
+        }
+
+        assert_eq!(0b1111, world.individuals[0].tracker.counters[0]);
+        assert_eq!(vec![0], surviving_indexes(&world, &criteria));
+
+        world.grid.increment_pheromone(99, 1);
+        update_trackers(&mut world, &criteria);
+
+        assert_eq!(vec![0], surviving_indexes(&world, &corners));
+        assert_eq!(0, surviving_indexes(&world, &criteria).len());
+    }
+
+    #[test]
+    fn relative_criteria() {
+        let mut world = World::new(Coord { x: 10, y: 10 });
+
+        for &grid_index in &[0, 1, 55, 99] {
+            let mut indiv = Individual::new();
+            indiv.grid_index = grid_index;
+            world.add_individual(indiv);
+        }
+
+        world.individuals[3].tracker.tiles_travelled = 10;
+        world.individuals[2].tracker.tiles_travelled = 5;
+
+        let closest = SurvivalCriteria::ClosestTo(Coord { x: 9, y: 9 }, 2);
+        assert_eq!(vec![2, 3], surviving_indexes(&world, &closest));
+
+        assert_eq!(vec![3], surviving_indexes(&world, &SurvivalCriteria::MostTravelled(0.25)));
+        assert_eq!(vec![2, 3], surviving_indexes(&world, &SurvivalCriteria::MostTravelled(0.5)));
+
+        let neighbours = SurvivalCriteria::MinNeighbours(1, 1);
+        assert_eq!(vec![0, 1], surviving_indexes(&world, &neighbours));
+
+        // Ranking is over the whole population, not only those matching the other side of And
+        let top_closest = SurvivalCriteria::TopPart(0.5).and(closest.clone());
+        assert_eq!(0, surviving_indexes(&world, &top_closest).len());
+
+        // No cell survives by position alone, unless the other side decides it
+        assert!(survive_cells(&world, &closest).is_empty());
+        assert!(survive_cells(&world, &!neighbours.clone()).is_empty());
+        assert!(survive_cells(&world, &top_closest).is_empty());
+        let top = survive_cells(&world, &SurvivalCriteria::TopPart(0.5));
+        assert_eq!(50, top.len());
+        assert_eq!(top, survive_cells(&world, &SurvivalCriteria::TopPart(0.5).or(closest)));
+    }
 }